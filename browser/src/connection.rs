@@ -1,17 +1,31 @@
 use std::collections::VecDeque;
 
-use noise_ix::{Initiator2, Transport};
+use chrono::{DateTime, Duration, Utc};
+use noise_ix::{ik, EphemeralKeypair, Hfs, Initiator2, PublicKey, StaticKeypair, Transport};
 use seed::{prelude::*, *};
-use serde_json::Value;
-use utils::{decode_cbor, encode_cbor};
+use serde::Serialize;
+use utils::protocol::{
+    fingerprint, TextMessage, FRAMED_PATH, HANDSHAKE_IK_HFS, HANDSHAKE_IX_HFS,
+    REKEY_AFTER_MESSAGES, REKEY_AFTER_MINUTES,
+};
+
+enum Handshake {
+    Ix(Initiator2<Hfs>),
+    Ik(ik::Initiator2<Hfs>),
+}
 
 pub struct Model {
-    private_key: [u8; 32],
+    private_key: StaticKeypair,
+    hub_key: Option<PublicKey>,
     text: String,
-    handshake: Option<Initiator2>,
+    handshake: Option<Handshake>,
     transport: Option<Transport>,
+    fingerprint: Option<String>,
+    error: Option<&'static str>,
+    sent: u64,
+    rekeyed_at: DateTime<Utc>,
     ws: WebSocket,
-    payload: VecDeque<Value>,
+    payload: VecDeque<Vec<u8>>,
 }
 
 #[derive(Clone)]
@@ -24,66 +38,133 @@ pub enum Msg {
 }
 
 impl Model {
-    pub fn init(key: [u8; 32], orders: &mut impl Orders<Msg>) -> Self {
+    pub fn init(key: StaticKeypair, orders: &mut impl Orders<Msg>) -> Self {
         Model {
             private_key: key,
+            hub_key: LocalStorage::get("hub_key").ok().map(PublicKey::from_bytes),
             text: String::new(),
             handshake: None,
             transport: None,
+            fingerprint: None,
+            error: None,
+            sent: 0,
+            rekeyed_at: Utc::now(),
             ws: ws_open(orders),
             payload: VecDeque::new(),
         }
     }
-    pub fn send(&mut self, payload: Value) {
+    pub fn send<T: Serialize>(&mut self, payload: &T) {
+        self.rekey_if_due();
         if let Some(ref mut state) = self.transport {
-            let mut buf = [0u8; 1024];
-            let written = encode_cbor(&payload, &mut buf).unwrap();
-
-            let mut message = vec![0u8; written + 16];
-            let len = state.write_message(&buf[..written], &mut message).unwrap();
-            self.ws.send_bytes(&message[..len]).unwrap();
+            let message = utils::ser::to_vec(payload)
+                .ok()
+                .and_then(|payload| state.write_message_vec(&payload).ok());
+            match message {
+                Some(message) => {
+                    self.ws.send_bytes(&message).unwrap();
+                    self.sent += 1;
+                }
+                None => log!("message too large to send"),
+            }
         };
     }
-    pub fn recv(&mut self) -> Option<Value> {
+    fn rekey_if_due(&mut self) {
+        if let Some(ref mut state) = self.transport {
+            let interval = Duration::minutes(REKEY_AFTER_MINUTES as i64);
+            if self.sent < REKEY_AFTER_MESSAGES && Utc::now() - self.rekeyed_at < interval {
+                return;
+            }
+
+            let message = state.write_message_vec(&[]).unwrap();
+            self.ws.send_bytes(&message).unwrap();
+
+            state.rekey_outgoing();
+            self.sent = 0;
+            self.rekeyed_at = Utc::now();
+        }
+    }
+    pub fn recv(&mut self) -> Option<Vec<u8>> {
         self.payload.pop_front()
     }
+    fn read(&mut self, message: &[u8]) -> Result<(), &'static str> {
+        if let Some(state) = self.handshake.take() {
+            let trans = match state {
+                Handshake::Ix(state) => {
+                    let (_, trans) = state
+                        .read_message_vec(message)
+                        .map_err(|_| "handshake failed")?;
+                    // pin the hub key for the next connection
+                    let hub_key = trans.remote_key();
+                    LocalStorage::insert("hub_key", hub_key.as_bytes()).unwrap();
+                    self.hub_key = Some(hub_key);
+                    trans
+                }
+                Handshake::Ik(state) => {
+                    let (_, trans) = state
+                        .read_message_vec(message)
+                        .map_err(|_| "handshake failed, hub key does not match")?;
+                    trans
+                }
+            };
+            self.fingerprint = Some(fingerprint(trans.handshake_hash()));
+            self.transport = Some(trans);
+            self.sent = 0;
+            self.rekeyed_at = Utc::now();
+        } else if let Some(ref mut state) = self.transport {
+            let payload = state
+                .read_message_vec(message)
+                .map_err(|_| "message could not be decrypted")?;
+            if payload.is_empty() {
+                state.rekey_incoming();
+            } else {
+                self.payload.push_back(payload)
+            }
+        }
+        Ok(())
+    }
     pub fn update(&mut self, msg: Msg, _orders: &mut impl Orders<Msg>) {
         match msg {
             Msg::Text(s) => self.text = s,
             Msg::Send => {
-                let json = serde_json::json!({
-                    "text": self.text
-                });
-                self.send(json);
+                let payload = TextMessage {
+                    text: self.text.clone(),
+                };
+                self.send(&payload);
             }
             Msg::Recv(message) => {
-                if let Some(state) = self.handshake.take() {
-                    let mut payload = vec![0u8; 512];
-                    let (_, trans) = state.read_message(&message[..], &mut payload).unwrap();
-
-                    self.transport = Some(trans);
-                } else {
-                    let mut payload = vec![0u8; message.len() - 16];
-                    if let Some(ref mut state) = self.transport {
-                        let len = state.read_message(&message, &mut payload).unwrap();
-                        let payload = decode_cbor(&payload[..len]).unwrap();
-                        self.payload.push_back(payload)
-                    }
+                if let Err(e) = self.read(&message) {
+                    // a session that failed once cannot be trusted again
+                    log!(e);
+                    self.error = Some(e);
+                    self.transport = None;
+                    self.fingerprint = None;
+                    let _ = self.ws.close(None, Some(e));
                 }
             }
             Msg::Disconnected => {
                 log!("disconnected");
                 self.transport = None;
+                self.fingerprint = None;
             }
             Msg::Connected => {
                 log!("connected");
-                let e = rand::random::<[u8; 32]>();
-                let init1 = noise_ix::initiator(e, self.private_key, &[]);
-
-                let mut message = vec![0u8; 64];
-                let (len, init2) = init1.write_message(&[], &mut message).unwrap();
-                self.ws.send_bytes(&message[..len]).unwrap();
-                self.handshake = Some(init2);
+                self.error = None;
+                let e = EphemeralKeypair::generate(&mut rand::rngs::OsRng);
+                // recorded sessions must stay private even if X25519 falls,
+                // so both handshakes run their hybrid variant
+                let (kind, message, handshake) = if let Some(hub_key) = self.hub_key {
+                    let init1 = ik::hybrid_initiator(e, &self.private_key, hub_key, &[]);
+                    let (message, init2) = init1.write_message_vec(&[]).unwrap();
+                    (HANDSHAKE_IK_HFS, message, Handshake::Ik(init2))
+                } else {
+                    let init1 = noise_ix::hybrid_initiator(e, &self.private_key, &[]);
+                    let (message, init2) = init1.write_message_vec(&[]).unwrap();
+                    (HANDSHAKE_IX_HFS, message, Handshake::Ix(init2))
+                };
+                let mut framed = vec![kind];
+                framed.extend_from_slice(&message);
+                self.ws.send_bytes(&framed).unwrap();
+                self.handshake = Some(handshake);
             }
         }
     }
@@ -118,6 +199,10 @@ impl Model {
                     ]
                 ]
             ],
+            self.fingerprint
+                .as_ref()
+                .map(|f| p![C!["help"], format!("session {}", f)]),
+            self.error.map(|e| p![C!["help is-danger"], e]),
         ]
     }
 }
@@ -127,7 +212,7 @@ pub fn ws_open(orders: &mut impl Orders<Msg>) -> WebSocket {
     let msg_send2 = orders.msg_sender();
     let msg_send3 = orders.msg_sender();
 
-    WebSocket::builder(format!("ws://{}/ws", &hostname()), orders)
+    WebSocket::builder(format!("ws://{}{}", &hostname(), FRAMED_PATH), orders)
         .on_message(move |m| {
             spawn_local(async move {
                 msg_send1(Some(Msg::Recv(m.bytes().await.unwrap())));
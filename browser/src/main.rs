@@ -1,17 +1,20 @@
 mod connection;
 
+use noise_ix::StaticKeypair;
 use seed::{prelude::*, *};
+use utils::protocol::TextMessage;
 
 // Use `wee_alloc` as the global allocator.
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 fn init(_: Url, orders: &mut impl Orders<Msg>) -> Model {
-    let static_key: [u8; 32] = if let Ok(x) = LocalStorage::get("static_dh") {
-        x
+    let static_key = if let Ok(secret) = LocalStorage::get("static_dh") {
+        StaticKeypair::from_secret(secret)
     } else {
-        LocalStorage::insert("static_dh", &rand::random::<[u8; 32]>()).unwrap();
-        LocalStorage::get("static_dh").unwrap()
+        let key = StaticKeypair::generate(&mut rand::rngs::OsRng);
+        LocalStorage::insert("static_dh", key.secret()).unwrap();
+        key
     };
     Model {
         connection: connection::Model::init(static_key, &mut orders.proxy(Msg::Connection)),
@@ -35,7 +38,13 @@ fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>) {
         }
     }
     if let Some(msg) = model.connection.recv() {
-        log!(msg)
+        match utils::diag::to_diag(&msg) {
+            Ok(text) => log!(text),
+            Err(e) => log!("invalid payload", e.to_string()),
+        }
+        if let Ok(message) = utils::de::from_slice::<TextMessage>(&msg) {
+            log!("text", message.text);
+        }
     }
 }
 
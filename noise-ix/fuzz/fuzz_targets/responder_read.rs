@@ -0,0 +1,28 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut payload = [0u8; 1024];
+
+    let resp = noise_ix::responder(
+        noise_ix::EphemeralKeypair::from_secret([2u8; 32]),
+        &noise_ix::StaticKeypair::from_secret([3u8; 32]),
+        &[],
+    );
+    let _ = resp.read_message(data, &mut payload);
+
+    let resp: noise_ix::Responder1<noise_ix::Psk0> = noise_ix::responder_psk(
+        noise_ix::EphemeralKeypair::from_secret([2u8; 32]),
+        &noise_ix::StaticKeypair::from_secret([3u8; 32]),
+        [4u8; 32],
+        &[],
+    );
+    let _ = resp.read_message(data, &mut payload);
+
+    let resp = noise_ix::ik::responder(
+        noise_ix::EphemeralKeypair::from_secret([2u8; 32]),
+        &noise_ix::StaticKeypair::from_secret([3u8; 32]),
+        &[],
+    );
+    let _ = resp.read_message(data, &mut payload);
+});
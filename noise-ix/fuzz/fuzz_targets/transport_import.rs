@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use noise_ix::{NoiseRead, NoiseWrite, Transport};
+
+fuzz_target!(|data: &[u8]| {
+    let key = [7u8; 32];
+    let _ = Transport::<noise_ix::ChaChaPoly>::import(&key, data);
+    let _ = NoiseRead::<noise_ix::ChaChaPoly>::import(&key, data);
+    let _ = NoiseWrite::<noise_ix::ChaChaPoly>::import(&key, data);
+});
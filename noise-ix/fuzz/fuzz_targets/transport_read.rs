@@ -0,0 +1,25 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut msg = [0u8; 200];
+    let mut payload = [0u8; 1024];
+
+    let init = noise_ix::initiator(
+        noise_ix::EphemeralKeypair::from_secret([0u8; 32]),
+        &noise_ix::StaticKeypair::from_secret([1u8; 32]),
+        &[],
+    );
+    let resp = noise_ix::responder(
+        noise_ix::EphemeralKeypair::from_secret([2u8; 32]),
+        &noise_ix::StaticKeypair::from_secret([3u8; 32]),
+        &[],
+    );
+    let (len, init) = init.write_message(&[], &mut msg).unwrap();
+    let (_, resp) = resp.read_message(&msg[..len], &mut payload).unwrap();
+    let (len, mut r_trans) = resp.write_message(&[], &mut msg).unwrap();
+    let (_, i_trans) = init.read_message(&msg[..len], &mut payload).unwrap();
+
+    let _ = r_trans.read_message(data, &mut payload);
+    let _ = i_trans.into_datagram().read_message(data, &mut payload);
+});
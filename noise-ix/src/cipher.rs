@@ -0,0 +1,82 @@
+use aead::{AeadInPlace, NewAead};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::ChaCha20Poly1305;
+
+use crate::{Error, TAG_LEN};
+
+pub const KEY_LEN: usize = 32;
+
+/// AEAD cipher of a Noise suite, keyed per call with a 256-bit key.
+pub trait Cipher {
+    const NAME: &'static str;
+
+    fn encrypt(k: &[u8; KEY_LEN], n: u64, ad: &[u8], in_out: &mut [u8]) -> [u8; TAG_LEN];
+    fn decrypt(
+        k: &[u8; KEY_LEN],
+        n: u64,
+        ad: &[u8],
+        in_out: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error>;
+}
+
+pub struct ChaChaPoly;
+pub struct AesGcm;
+
+impl Cipher for ChaChaPoly {
+    const NAME: &'static str = "ChaChaPoly";
+
+    fn encrypt(k: &[u8; KEY_LEN], n: u64, ad: &[u8], in_out: &mut [u8]) -> [u8; TAG_LEN] {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&n.to_le_bytes());
+
+        ChaCha20Poly1305::new(k.into())
+            .encrypt_in_place_detached(&nonce.into(), ad, in_out)
+            .unwrap()
+            .into()
+    }
+
+    fn decrypt(
+        k: &[u8; KEY_LEN],
+        n: u64,
+        ad: &[u8],
+        in_out: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&n.to_le_bytes());
+
+        ChaCha20Poly1305::new(k.into())
+            .decrypt_in_place_detached(&nonce.into(), ad, in_out, tag.into())
+            .map_err(|_| Error::Decrypt)
+    }
+}
+
+impl Cipher for AesGcm {
+    const NAME: &'static str = "AESGCM";
+
+    fn encrypt(k: &[u8; KEY_LEN], n: u64, ad: &[u8], in_out: &mut [u8]) -> [u8; TAG_LEN] {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&n.to_be_bytes());
+
+        Aes256Gcm::new(k.into())
+            .encrypt_in_place_detached(&nonce.into(), ad, in_out)
+            .unwrap()
+            .into()
+    }
+
+    fn decrypt(
+        k: &[u8; KEY_LEN],
+        n: u64,
+        ad: &[u8],
+        in_out: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&n.to_be_bytes());
+
+        Aes256Gcm::new(k.into())
+            .decrypt_in_place_detached(&nonce.into(), ad, in_out, tag.into())
+            .map_err(|_| Error::Decrypt)
+    }
+}
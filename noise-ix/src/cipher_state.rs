@@ -1,23 +1,51 @@
-use aead::{AeadInPlace, NewAead};
-use chacha20poly1305::ChaCha20Poly1305;
+use core::{convert::TryInto, marker::PhantomData};
 
-use crate::TAG_LEN;
+use zeroize::Zeroizing;
 
-pub(crate) struct CipherState {
-    c: ChaCha20Poly1305,
+use crate::{
+    cipher::{Cipher, KEY_LEN},
+    TAG_LEN,
+};
+
+pub(crate) const STATE_LEN: usize = KEY_LEN + 8;
+
+pub(crate) struct CipherState<C: Cipher> {
+    k: Zeroizing<[u8; KEY_LEN]>,
     pub(crate) n: u64,
+    cipher: PhantomData<C>,
 }
 
-impl CipherState {
-    pub(crate) fn new(k: [u8; 32]) -> Self {
+impl<C: Cipher> CipherState<C> {
+    pub(crate) fn new(k: &[u8]) -> Self {
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(&k[..KEY_LEN]);
         Self {
-            c: ChaCha20Poly1305::new(&k.into()),
+            k: key,
             n: 0,
+            cipher: PhantomData,
         }
     }
+    #[cfg(test)]
+    pub(crate) fn key(&self) -> [u8; KEY_LEN] {
+        *self.k
+    }
+    pub(crate) fn import(state: &[u8]) -> Self {
+        let mut cipher = Self::new(&state[..KEY_LEN]);
+        cipher.n = u64::from_be_bytes(state[KEY_LEN..STATE_LEN].try_into().unwrap());
+        cipher
+    }
+    pub(crate) fn export(&self, state: &mut [u8]) {
+        state[..KEY_LEN].copy_from_slice(&*self.k);
+        state[KEY_LEN..STATE_LEN].copy_from_slice(&self.n.to_be_bytes());
+    }
     pub(crate) fn set_nonce(&mut self, nonce: u64) {
         self.n = nonce
     }
+    pub(crate) fn rekey(&mut self) {
+        let mut k = Zeroizing::new([0u8; KEY_LEN]);
+        C::encrypt(&self.k, u64::MAX, &[], &mut *k);
+        self.k = k;
+    }
     pub(crate) fn encrypt_with_ad(
         &mut self,
         ad: &[u8],
@@ -28,19 +56,16 @@ impl CipherState {
         if ciphertext.len() < len {
             return Err(crate::Error::Input);
         }
+        if self.n == u64::MAX {
+            return Err(crate::Error::Nonce);
+        }
 
         let (ciphertext, rest) = ciphertext.split_at_mut(plaintext.len());
         let (ciphertext_mac, _) = rest.split_at_mut(TAG_LEN);
 
         ciphertext.copy_from_slice(plaintext);
 
-        let mut nonce_bytes = [0u8; 12];
-        nonce_bytes[4..].copy_from_slice(&self.n.to_le_bytes());
-
-        let tag = self
-            .c
-            .encrypt_in_place_detached(&nonce_bytes.into(), ad, ciphertext)
-            .unwrap();
+        let tag = C::encrypt(&self.k, self.n, ad, ciphertext);
 
         self.n += 1;
         ciphertext_mac.copy_from_slice(&tag);
@@ -59,18 +84,16 @@ impl CipherState {
         if plaintext.len() < len {
             return Err(crate::Error::Input);
         }
+        if self.n == u64::MAX {
+            return Err(crate::Error::Nonce);
+        }
 
         let (ciphertext, ciphertext_mac) = ciphertext.split_at(len);
         let (plaintext, _) = plaintext.split_at_mut(len);
 
         plaintext.copy_from_slice(ciphertext);
 
-        let mut nonce_bytes = [0u8; 12];
-        nonce_bytes[4..].copy_from_slice(&self.n.to_le_bytes());
-
-        self.c
-            .decrypt_in_place_detached(&nonce_bytes.into(), ad, plaintext, ciphertext_mac.into())
-            .map_err(|_| crate::Error::Decrypt)?;
+        C::decrypt(&self.k, self.n, ad, plaintext, ciphertext_mac)?;
 
         self.n += 1;
         Ok(len)
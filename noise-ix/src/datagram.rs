@@ -0,0 +1,211 @@
+//! Transport mode for unordered, lossy links such as UDP.
+//!
+//! Every message is prefixed with the big-endian 64-bit nonce it was
+//! encrypted with, so messages can be decrypted in any order. The receiver
+//! remembers the last `REPLAY_WINDOW` nonces and rejects duplicates and
+//! anything older with `Error::Replay`.
+
+use core::convert::TryInto;
+
+use crate::{
+    symmetric_state::HandshakeHash, ChaChaPoly, Cipher, CipherState, Dh, Error, PublicKey,
+    Transport, TAG_LEN, X25519,
+};
+
+pub const NONCE_LEN: usize = 8;
+pub const REPLAY_WINDOW: u64 = 64;
+
+pub struct DatagramTransport<C: Cipher = ChaChaPoly, D: Dh = X25519> {
+    rs: D::Key,
+    h: HandshakeHash,
+    send: CipherState<C>,
+    recv: CipherState<C>,
+    window: ReplayWindow,
+}
+
+#[derive(Default)]
+struct ReplayWindow {
+    // one past the highest nonce accepted so far
+    next: u64,
+    // bit i is set when nonce `next - 1 - i` has been accepted
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    fn check(&self, nonce: u64) -> Result<(), Error> {
+        if nonce >= self.next {
+            return Ok(());
+        }
+        let age = self.next - 1 - nonce;
+        if age >= REPLAY_WINDOW || self.bitmap & (1 << age) != 0 {
+            return Err(Error::Replay);
+        }
+        Ok(())
+    }
+    fn accept(&mut self, nonce: u64) {
+        if nonce >= self.next {
+            let shift = nonce - self.next + 1;
+            self.bitmap = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.bitmap << shift
+            };
+            self.bitmap |= 1;
+            self.next = nonce + 1;
+        } else {
+            self.bitmap |= 1 << (self.next - 1 - nonce);
+        }
+    }
+}
+
+impl<C: Cipher, D: Dh> Transport<C, D> {
+    pub fn into_datagram(self) -> DatagramTransport<C, D> {
+        DatagramTransport {
+            rs: self.rs,
+            h: self.h,
+            send: self.send,
+            recv: self.recv,
+            window: ReplayWindow::default(),
+        }
+    }
+}
+
+impl<C: Cipher> DatagramTransport<C> {
+    pub fn remote_key(&self) -> PublicKey {
+        PublicKey::from(self.rs)
+    }
+}
+
+impl<C: Cipher, D: Dh> DatagramTransport<C, D> {
+    pub const fn overhead() -> usize {
+        NONCE_LEN + TAG_LEN
+    }
+    pub fn remote_static(&self) -> D::Key {
+        self.rs
+    }
+    pub fn handshake_hash(&self) -> &[u8] {
+        self.h.as_slice()
+    }
+    pub fn send_nonce(&self) -> u64 {
+        self.send.n
+    }
+    pub fn read_message(&mut self, message: &[u8], payload: &mut [u8]) -> Result<usize, Error> {
+        if message.len() < Self::overhead() {
+            return Err(Error::Input);
+        }
+        let (nonce, message) = message.split_at(NONCE_LEN);
+        let nonce = u64::from_be_bytes(nonce.try_into().unwrap());
+
+        self.window.check(nonce)?;
+        self.recv.set_nonce(nonce);
+        let len = self.recv.decrypt_with_ad(&[], message, payload)?;
+        // only authentic messages may move the window
+        self.window.accept(nonce);
+        Ok(len)
+    }
+    pub fn write_message(&mut self, payload: &[u8], message: &mut [u8]) -> Result<usize, Error> {
+        if message.len() < NONCE_LEN {
+            return Err(Error::Input);
+        }
+        let (nonce, message) = message.split_at_mut(NONCE_LEN);
+        nonce.copy_from_slice(&self.send.n.to_be_bytes());
+
+        let len = self.send.encrypt_with_ad(&[], payload, message)?;
+        Ok(NONCE_LEN + len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn datagrams() -> (DatagramTransport, DatagramTransport) {
+        let mut msg = [0u8; 200];
+        let mut payload = [0u8; 200];
+
+        let init = initiator(
+            EphemeralKeypair::from_secret([0u8; 32]),
+            &StaticKeypair::from_secret([1u8; 32]),
+            &[],
+        );
+        let resp = responder(
+            EphemeralKeypair::from_secret([2u8; 32]),
+            &StaticKeypair::from_secret([3u8; 32]),
+            &[],
+        );
+        let (len, init) = init.write_message(&[], &mut msg).unwrap();
+        let (_, resp) = resp.read_message(&msg[..len], &mut payload).unwrap();
+        let (len, r_trans) = resp.write_message(&[], &mut msg).unwrap();
+        let (_, i_trans) = init.read_message(&msg[..len], &mut payload).unwrap();
+        (i_trans.into_datagram(), r_trans.into_datagram())
+    }
+
+    #[test]
+    fn test_datagram_out_of_order() {
+        let (mut i_trans, mut r_trans) = datagrams();
+        let mut payload = [0u8; 64];
+
+        let mut msgs = [[0u8; 64]; 3];
+        let mut lens = [0usize; 3];
+        for (i, msg) in msgs.iter_mut().enumerate() {
+            lens[i] = i_trans.write_message(&[i as u8; 4], msg).unwrap();
+        }
+        for i in [2, 0, 1] {
+            let len = r_trans
+                .read_message(&msgs[i][..lens[i]], &mut payload)
+                .unwrap();
+            assert_eq!(&payload[..len], &[i as u8; 4]);
+        }
+    }
+
+    #[test]
+    fn test_datagram_replay() {
+        let (mut i_trans, mut r_trans) = datagrams();
+        let mut msg = [0u8; 64];
+        let mut payload = [0u8; 64];
+
+        let len = i_trans.write_message(b"once", &mut msg).unwrap();
+        r_trans.read_message(&msg[..len], &mut payload).unwrap();
+        assert!(matches!(
+            r_trans.read_message(&msg[..len], &mut payload),
+            Err(Error::Replay)
+        ));
+    }
+
+    #[test]
+    fn test_datagram_too_old() {
+        let (mut i_trans, mut r_trans) = datagrams();
+        let mut old = [0u8; 64];
+        let mut msg = [0u8; 64];
+        let mut payload = [0u8; 64];
+
+        let old_len = i_trans.write_message(b"old", &mut old).unwrap();
+        for _ in 0..REPLAY_WINDOW {
+            let len = i_trans.write_message(b"new", &mut msg).unwrap();
+            r_trans.read_message(&msg[..len], &mut payload).unwrap();
+        }
+        assert!(matches!(
+            r_trans.read_message(&old[..old_len], &mut payload),
+            Err(Error::Replay)
+        ));
+    }
+
+    #[test]
+    fn test_datagram_forged_nonce() {
+        let (mut i_trans, mut r_trans) = datagrams();
+        let mut msg = [0u8; 64];
+        let mut payload = [0u8; 64];
+
+        let len = i_trans.write_message(b"real", &mut msg).unwrap();
+        let mut forged = msg;
+        forged[..8].copy_from_slice(&1000u64.to_be_bytes());
+        assert!(matches!(
+            r_trans.read_message(&forged[..len], &mut payload),
+            Err(Error::Decrypt)
+        ));
+
+        // the forged nonce must not have pushed the real one out of the window
+        let len = r_trans.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"real");
+    }
+}
@@ -0,0 +1,140 @@
+use core::convert::TryFrom;
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::Error;
+
+pub(crate) const MAX_DH_LEN: usize = 56;
+
+/// Diffie-Hellman function of a Noise suite. Secret keys, public keys and
+/// shared secrets are all `Key`, a `[u8; LEN]`.
+pub trait Dh {
+    const NAME: &'static str;
+    const LEN: usize;
+    type Key: Copy + AsRef<[u8]> + AsMut<[u8]> + Zeroize + for<'a> TryFrom<&'a [u8]>;
+
+    fn pub_key(secret: &Self::Key) -> Self::Key;
+    /// Fails with `Error::Dh` if `public` is a low order point.
+    fn dh(secret: &Self::Key, public: &Self::Key) -> Result<Zeroizing<Self::Key>, Error>;
+}
+
+/// The local static key of a handshake. A key kept in a secure element or
+/// a key store only has to give its public key and run `dh`; its secret
+/// never needs to be in memory.
+pub trait StaticKey<D: Dh> {
+    fn public_key(&self) -> D::Key;
+    fn dh(&self, public: &D::Key) -> Result<Zeroizing<D::Key>, Error>;
+}
+
+/// A static key held in memory, wiped on drop.
+pub struct SecretKey<D: Dh> {
+    secret: Zeroizing<D::Key>,
+    public: D::Key,
+}
+
+impl<D: Dh> SecretKey<D> {
+    pub fn from_secret(secret: D::Key) -> Self {
+        Self::from_zeroizing(Zeroizing::new(secret))
+    }
+    pub(crate) fn from_zeroizing(secret: Zeroizing<D::Key>) -> Self {
+        Self {
+            public: D::pub_key(&secret),
+            secret,
+        }
+    }
+}
+
+impl<D: Dh> StaticKey<D> for SecretKey<D> {
+    fn public_key(&self) -> D::Key {
+        self.public
+    }
+    fn dh(&self, public: &D::Key) -> Result<Zeroizing<D::Key>, Error> {
+        D::dh(&self.secret, public)
+    }
+}
+
+pub(crate) fn key<D: Dh>(bytes: &[u8]) -> Result<D::Key, Error> {
+    D::Key::try_from(bytes).map_err(|_| Error::Input)
+}
+
+fn non_zero<K: AsRef<[u8]> + Zeroize>(shared: K) -> Result<Zeroizing<K>, Error> {
+    let shared = Zeroizing::new(shared);
+    if shared.as_ref().iter().any(|b| *b != 0u8) {
+        Ok(shared)
+    } else {
+        Err(Error::Dh)
+    }
+}
+
+pub struct X25519;
+
+impl Dh for X25519 {
+    const NAME: &'static str = "25519";
+    const LEN: usize = 32;
+    type Key = [u8; 32];
+
+    fn pub_key(secret: &[u8; 32]) -> [u8; 32] {
+        use x25519_dalek::{PublicKey, StaticSecret};
+        PublicKey::from(&StaticSecret::from(*secret)).to_bytes()
+    }
+    fn dh(secret: &[u8; 32], public: &[u8; 32]) -> Result<Zeroizing<[u8; 32]>, Error> {
+        use x25519_dalek::{PublicKey, StaticSecret};
+        let shared = StaticSecret::from(*secret).diffie_hellman(&PublicKey::from(*public));
+        non_zero(*shared.as_bytes())
+    }
+}
+
+#[cfg(feature = "curve448")]
+pub struct X448;
+
+#[cfg(feature = "curve448")]
+impl Dh for X448 {
+    const NAME: &'static str = "448";
+    const LEN: usize = 56;
+    type Key = [u8; 56];
+
+    fn pub_key(secret: &[u8; 56]) -> [u8; 56] {
+        use x448::{PublicKey, Secret};
+        *PublicKey::from(&Secret::from(*secret)).as_bytes()
+    }
+    fn dh(secret: &[u8; 56], public: &[u8; 56]) -> Result<Zeroizing<[u8; 56]>, Error> {
+        // `x448` rejects low order points itself
+        non_zero(x448::x448(*secret, *public).ok_or(Error::Dh)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "curve448")]
+    fn hex<const N: usize>(s: &str) -> [u8; N] {
+        let mut out = [0u8; N];
+        for (i, b) in out.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_x25519_low_order() {
+        let mut secret = [0u8; 32];
+        secret[0] = 1;
+        assert!(matches!(X25519::dh(&secret, &[0u8; 32]), Err(Error::Dh)));
+    }
+
+    // RFC 7748, section 6.2
+    #[cfg(feature = "curve448")]
+    #[test]
+    fn test_x448_rfc7748() {
+        let alice: [u8; 56] = hex("9a8f4925d1519f5775cf46b04b5800d4ee9ee8bae8bc5565d498c28dd9c9baf574a9419744897391006382a6f127ab1d9ac2d8c0a598726b");
+        let bob: [u8; 56] = hex("1c306a7ac2a0e2e0990b294470cba339e6453772b075811d8fad0d1d6927c120bb5ee8972b0d3e21374c9c921b09d1b0366f10b65173992d");
+        let alice_pub: [u8; 56] = hex("9b08f7cc31b7e3e67d22d5aea121074a273bd2b83de09c63faa73d2c22c5d9bbc836647241d953d40c5b12da88120d53177f80e532c41fa0");
+        let shared: [u8; 56] = hex("07fff4181ac6cc95ec1c16a94a0f74d12da232ce40a77552281d282bb60c0b56fd2464c335543936521c24403085d59a449a5037514a879d");
+
+        assert_eq!(X448::pub_key(&alice), alice_pub);
+        assert_eq!(*X448::dh(&bob, &alice_pub).unwrap(), shared);
+        assert_eq!(*X448::dh(&alice, &X448::pub_key(&bob)).unwrap(), shared);
+        assert!(matches!(X448::dh(&alice, &[0u8; 56]), Err(Error::Dh)));
+    }
+}
@@ -1,230 +1,332 @@
+use core::marker::PhantomData;
+
+use zeroize::Zeroizing;
+
 use crate::{
-    x25519::{pub_key, x25519},
-    Error, SymmetricState, Transport, TAG_LEN,
+    pattern::{self, HandshakePattern},
+    Blake2s, ChaChaPoly, Cipher, EphemeralKeypair, Error, HandshakeKeys, HandshakeState, Hash,
+    PublicKey, StaticKeypair, Transport,
 };
 
 pub(crate) const DH_LEN: usize = 32;
+pub(crate) const PSK_LEN: usize = 32;
+
+/// Which IX variant the typestates below run.
+pub trait Mode {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern;
+    const PATTERN: &'static str = Self::HANDSHAKE_PATTERN.name;
+}
+
+/// A variant that mixes in a pre-shared key, started with `with_psk`.
+pub trait PskMode: Mode {}
+
+/// A variant without a pre-shared key, started with `new`.
+pub trait Unkeyed: Mode {}
+
+pub struct NoPsk;
+pub struct Psk0;
+pub struct Psk2;
+/// IXhfs: IX with an additional ML-KEM exchange on the ephemeral keys.
+#[cfg(feature = "hybrid")]
+pub struct Hfs;
+
+impl Mode for NoPsk {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern = &pattern::IX;
+}
+
+impl Unkeyed for NoPsk {}
+
+impl Mode for Psk0 {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern = &pattern::IXPSK0;
+}
+
+impl PskMode for Psk0 {}
+
+impl Mode for Psk2 {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern = &pattern::IXPSK2;
+}
+
+impl PskMode for Psk2 {}
+
+#[cfg(feature = "hybrid")]
+impl Mode for Hfs {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern = &pattern::IXHFS;
+}
+
+#[cfg(feature = "hybrid")]
+impl Unkeyed for Hfs {}
+
+// The IX typestates below only make the message order part of the type;
+// the handshake itself is run by `HandshakeState`.
 
-pub struct Initiator1 {
-    pub(crate) e: [u8; DH_LEN],
-    pub(crate) s: [u8; DH_LEN],
-    pub(crate) c: SymmetricState,
+pub struct Initiator1<P: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<P>,
 }
 
-pub struct Initiator2 {
-    e: [u8; DH_LEN],
-    s: [u8; DH_LEN],
-    c: SymmetricState,
+pub struct Initiator2<P: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<P>,
 }
 
-pub struct Responder1 {
-    pub(crate) e: [u8; DH_LEN],
-    pub(crate) s: [u8; DH_LEN],
-    pub(crate) re: [u8; DH_LEN],
-    pub(crate) rs: [u8; DH_LEN],
-    pub(crate) c: SymmetricState,
+pub struct Responder1<P: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<P>,
 }
 
-pub struct Responder2 {
-    e: [u8; DH_LEN],
-    s: [u8; DH_LEN],
-    re: [u8; DH_LEN],
-    rs: [u8; DH_LEN],
-    c: SymmetricState,
+pub struct Responder2<P: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<P>,
 }
 
-impl Initiator1 {
+fn ix_state<P: Mode, C: Cipher, H: Hash>(
+    initiator: bool,
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    psk: Option<[u8; PSK_LEN]>,
+    prologue: &[u8],
+) -> HandshakeState<C, H> {
+    #[cfg(feature = "hybrid")]
+    let kem_seed = Some(e.kem_seed());
+    #[cfg(not(feature = "hybrid"))]
+    let kem_seed = None;
+    let keys = HandshakeKeys {
+        s: Some(s.to_secret()),
+        e: Some(e.into_secret()),
+        psk: psk.map(Zeroizing::new),
+        kem_seed,
+        ..HandshakeKeys::default()
+    };
+    // IX has no pre-messages, so every key it needs is given here
+    HandshakeState::new(P::HANDSHAKE_PATTERN, initiator, prologue, keys).unwrap()
+}
+
+impl<P: Unkeyed, C: Cipher, H: Hash> Initiator1<P, C, H> {
+    pub fn new(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Self {
+        Self {
+            state: ix_state::<P, C, H>(true, e, s, None, prologue),
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<P: PskMode, C: Cipher, H: Hash> Initiator1<P, C, H> {
+    pub fn with_psk(
+        e: EphemeralKeypair,
+        s: &StaticKeypair,
+        psk: [u8; PSK_LEN],
+        prologue: &[u8],
+    ) -> Self {
+        Self {
+            state: ix_state::<P, C, H>(true, e, s, Some(psk), prologue),
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<P: Mode, C: Cipher, H: Hash> Initiator1<P, C, H> {
     pub const fn overhead() -> usize {
-        DH_LEN * 2
+        P::HANDSHAKE_PATTERN.overhead(0)
     }
 
     pub fn write_message(
         mut self,
         payload: &[u8],
         message: &mut [u8],
-    ) -> Result<(usize, Initiator2), Error> {
-        if message.len() < Self::overhead() + payload.len() {
-            return Err(Error::Input);
-        }
-
-        let (msg_e, rest) = &mut message.split_at_mut(DH_LEN);
-        let (msg_s, rest) = &mut rest.split_at_mut(DH_LEN);
-        let (msg_p, _) = &mut rest.split_at_mut(payload.len());
-
-        // e
-        let pub_e = pub_key(self.e);
-        msg_e.copy_from_slice(&pub_e);
-        self.c.mix_hash(msg_e);
-
-        // s
-        let pub_s = pub_key(self.s);
-        msg_s.copy_from_slice(&pub_s);
-        self.c.mix_hash(msg_s);
-
-        // payload
-        msg_p.copy_from_slice(payload);
-        self.c.mix_hash(msg_p);
-
+    ) -> Result<(usize, Initiator2<P, C, H>), Error> {
+        let len = self.state.write_message(payload, message)?;
         Ok((
-            Self::overhead() + payload.len(),
+            len,
             Initiator2 {
-                e: self.e,
-                s: self.s,
-                c: self.c,
+                state: self.state,
+                mode: PhantomData,
             },
         ))
     }
 }
-impl Responder1 {
+
+impl<P: Unkeyed, C: Cipher, H: Hash> Responder1<P, C, H> {
+    pub fn new(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Self {
+        Self {
+            state: ix_state::<P, C, H>(false, e, s, None, prologue),
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<P: PskMode, C: Cipher, H: Hash> Responder1<P, C, H> {
+    pub fn with_psk(
+        e: EphemeralKeypair,
+        s: &StaticKeypair,
+        psk: [u8; PSK_LEN],
+        prologue: &[u8],
+    ) -> Self {
+        Self {
+            state: ix_state::<P, C, H>(false, e, s, Some(psk), prologue),
+            mode: PhantomData,
+        }
+    }
+}
+
+impl<P: Mode, C: Cipher, H: Hash> Responder1<P, C, H> {
     pub const fn overhead() -> usize {
-        DH_LEN * 2
+        P::HANDSHAKE_PATTERN.overhead(0)
     }
 
     pub fn read_message(
         mut self,
         message: &[u8],
         payload: &mut [u8],
-    ) -> Result<(usize, Responder2), Error> {
-        if message.len() < Self::overhead() {
-            return Err(Error::Input);
-        }
-        if payload.len() < message.len() - Self::overhead() {
-            return Err(Error::Input);
-        }
-
-        let (msg_re, rest) = message.split_at(DH_LEN);
-        let (msg_rs, msg_rp) = rest.split_at(DH_LEN);
-
-        let (payload, _) = payload.split_at_mut(msg_rp.len());
-
-        // e
-        //
-        self.re.copy_from_slice(msg_re);
-        self.c.mix_hash(&self.re);
-
-        // s
-        self.rs.copy_from_slice(msg_rs);
-        self.c.mix_hash(&self.rs);
-
-        // payload
-        payload.copy_from_slice(msg_rp);
-        self.c.mix_hash(payload);
-
+    ) -> Result<(usize, Responder2<P, C, H>), Error> {
+        let len = self.state.read_message(message, payload)?;
         Ok((
-            payload.len(),
+            len,
             Responder2 {
-                e: self.e,
-                s: self.s,
-                re: self.re,
-                rs: self.rs,
-                c: self.c,
+                state: self.state,
+                mode: PhantomData,
             },
         ))
     }
 }
 
-impl Responder2 {
+impl<P: Mode, C: Cipher, H: Hash> Responder2<P, C, H> {
     pub const fn overhead() -> usize {
-        DH_LEN + DH_LEN + TAG_LEN + TAG_LEN
+        P::HANDSHAKE_PATTERN.overhead(1)
     }
-    pub fn remote_key(&self) -> [u8; 32] {
-        self.rs
+    pub fn remote_key(&self) -> PublicKey {
+        PublicKey::from(self.state.remote_static().unwrap_or_default())
     }
     pub fn write_message(
         mut self,
         payload: &[u8],
         message: &mut [u8],
-    ) -> Result<(usize, Transport), Error> {
-        if message.len() < Self::overhead() + payload.len() {
-            return Err(Error::Input);
-        }
-
-        let (msg_e, rest) = message.split_at_mut(DH_LEN);
-        let (msg_s, rest) = rest.split_at_mut(DH_LEN + TAG_LEN);
-        let (msg_p, _) = rest.split_at_mut(payload.len() + TAG_LEN);
-
-        // e
-        let pub_e = pub_key(self.e);
-        msg_e.copy_from_slice(&pub_e);
-        self.c.mix_hash(msg_e);
-
-        // ee, se
-        self.c.mix_key(&x25519(self.e, self.re)?);
-        self.c.mix_key(&x25519(self.e, self.rs)?);
-
-        // s
-        let pub_s = pub_key(self.s);
-        self.c.encrypt_and_hash(&pub_s, msg_s)?;
-
-        // es
-        self.c.mix_key(&x25519(self.s, self.re)?);
-
-        // payload
-        self.c.encrypt_and_hash(payload, msg_p)?;
-
-        // split
-        let (c1, c2) = self.c.split();
-        Ok((
-            Self::overhead() + payload.len(),
-            Transport {
-                rs: self.rs,
-                send: c2,
-                recv: c1,
-            },
-        ))
+    ) -> Result<(usize, Transport<C>), Error> {
+        let len = self.state.write_message(payload, message)?;
+        Ok((len, self.state.into_transport()?))
     }
 }
 
-impl Initiator2 {
+impl<P: Mode, C: Cipher, H: Hash> Initiator2<P, C, H> {
     pub const fn overhead() -> usize {
-        DH_LEN + DH_LEN + TAG_LEN + TAG_LEN
+        P::HANDSHAKE_PATTERN.overhead(1)
     }
     pub fn read_message(
         mut self,
         message: &[u8],
         payload: &mut [u8],
-    ) -> Result<(usize, Transport), Error> {
-        if message.len() < Self::overhead() {
-            return Err(Error::Input);
+    ) -> Result<(usize, Transport<C>), Error> {
+        let len = self.state.read_message(message, payload)?;
+        Ok((len, self.state.into_transport()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use core::{
+        mem::{size_of, MaybeUninit},
+        ptr, slice,
+    };
+
+    // Drops `state` in place and checks that none of `secrets` is left in
+    // the memory it occupied.
+    fn assert_wiped<T>(state: T, secrets: &[&[u8]]) {
+        let mut slot = MaybeUninit::new(state);
+        let state = slot.as_mut_ptr();
+        let bytes = || unsafe { slice::from_raw_parts(state as *const u8, size_of::<T>()) };
+        let contains = |secret: &[u8]| bytes().windows(secret.len()).any(|w| w == secret);
+
+        for secret in secrets {
+            assert!(contains(secret));
         }
-        if payload.len() < message.len() - Self::overhead() {
-            return Err(Error::Input);
+        unsafe { ptr::drop_in_place(state) };
+        for secret in secrets {
+            assert!(!contains(secret));
         }
+    }
 
-        let (msg_e, rest) = message.split_at(DH_LEN);
-        let (msg_s, msg_p) = rest.split_at(DH_LEN + TAG_LEN);
-
-        let (payload, _) = payload.split_at_mut(msg_p.len() - TAG_LEN);
-
-        let mut re = [0u8; DH_LEN];
-        let mut rs = [0u8; DH_LEN];
-
-        // e
-        re.copy_from_slice(msg_e);
-        self.c.mix_hash(msg_e);
-
-        // ee, se
-        self.c.mix_key(&x25519(self.e, re)?);
-        self.c.mix_key(&x25519(self.s, re)?);
+    #[test]
+    fn test_keys_wiped() {
+        let keys: HandshakeKeys = HandshakeKeys {
+            s: Some(Zeroizing::new([1u8; 32])),
+            e: Some(Zeroizing::new([2u8; 32])),
+            psk: Some(Zeroizing::new([3u8; 32])),
+            kem_seed: Some(Zeroizing::new([4u8; 64])),
+            ..HandshakeKeys::default()
+        };
+        assert_wiped(keys, &[&[1u8; 32], &[2u8; 32], &[3u8; 32], &[4u8; 64]]);
+    }
 
-        // s
-        self.c.decrypt_and_hash(msg_s, &mut rs)?;
+    #[test]
+    fn test_initiator_wiped() {
+        let init: Initiator1<Psk0> = Initiator1::with_psk(
+            EphemeralKeypair::from_secret([1u8; 32]),
+            &StaticKeypair::from_secret([2u8; 32]),
+            [3u8; 32],
+            &[],
+        );
+        assert_wiped(init, &[&[1u8; 32], &[2u8; 32], &[3u8; 32]]);
+    }
 
-        // es
-        self.c.mix_key(&x25519(self.e, rs)?);
+    #[test]
+    fn test_responder_wiped() {
+        let init: Initiator1<Psk0> = Initiator1::with_psk(
+            EphemeralKeypair::from_secret([1u8; 32]),
+            &StaticKeypair::from_secret([2u8; 32]),
+            [3u8; 32],
+            &[],
+        );
+        let resp: Responder1<Psk0> = Responder1::with_psk(
+            EphemeralKeypair::from_secret([4u8; 32]),
+            &StaticKeypair::from_secret([5u8; 32]),
+            [3u8; 32],
+            &[],
+        );
+
+        let mut message = [0u8; 256];
+        let (len, _) = init.write_message(&[], &mut message).unwrap();
+        let (_, resp) = resp.read_message(&message[..len], &mut []).unwrap();
+
+        let (ck, k) = resp.state.c.keys();
+        assert_wiped(
+            resp,
+            &[&[4u8; 32], &[5u8; 32], &[3u8; 32], &ck[..Blake2s::LEN], &k],
+        );
+    }
 
-        // payload
-        self.c.decrypt_and_hash(msg_p, payload)?;
+    #[test]
+    fn test_transport_wiped() {
+        let init: Initiator1 = Initiator1::new(
+            EphemeralKeypair::from_secret([1u8; 32]),
+            &StaticKeypair::from_secret([2u8; 32]),
+            &[],
+        );
+        let resp: Responder1 = Responder1::new(
+            EphemeralKeypair::from_secret([4u8; 32]),
+            &StaticKeypair::from_secret([5u8; 32]),
+            &[],
+        );
+
+        let mut message = [0u8; 256];
+        let (len, _) = init.write_message(&[], &mut message).unwrap();
+        let (_, resp) = resp.read_message(&message[..len], &mut []).unwrap();
+        let (_, trans) = resp.write_message(&[], &mut message).unwrap();
+
+        let (send, recv) = (trans.send.key(), trans.recv.key());
+        assert_wiped(trans, &[&send, &recv]);
+    }
 
-        // split
-        let (c1, c2) = self.c.split();
-        Ok((
-            payload.len(),
-            Transport {
-                rs,
-                send: c1,
-                recv: c2,
-            },
-        ))
+    proptest::proptest! {
+        #[test]
+        fn prop_responder_read(
+            message in proptest::collection::vec(proptest::num::u8::ANY, 0..256),
+            payload_len in 0..256usize,
+        ) {
+            let mut payload = [0u8; 256];
+            let resp: Responder1 = Responder1::new(EphemeralKeypair::from_secret([4u8; 32]), &StaticKeypair::from_secret([5u8; 32]), &[]);
+            let _ = resp.read_message(&message, &mut payload[..payload_len]);
+            let resp: Responder1<Psk0> = Responder1::with_psk(EphemeralKeypair::from_secret([4u8; 32]), &StaticKeypair::from_secret([5u8; 32]), [3u8; 32], &[]);
+            let _ = resp.read_message(&message, &mut payload[..payload_len]);
+        }
     }
 }
@@ -0,0 +1,700 @@
+use zeroize::Zeroizing;
+
+#[cfg(feature = "hybrid")]
+use crate::kem;
+use crate::{
+    dh::{key, MAX_DH_LEN},
+    handshake::PSK_LEN,
+    pattern::{HandshakePattern, Token},
+    Blake2s, ChaChaPoly, Cipher, Dh, Error, Hash, SecretKey, StaticKey, SymmetricState, Transport,
+    X25519,
+};
+
+/// Keys for one side of a handshake. Which ones are needed depends on the
+/// pattern; unused keys are ignored. Secrets are wiped on drop.
+pub struct HandshakeKeys<D: Dh = X25519> {
+    pub s: Option<Zeroizing<D::Key>>,
+    pub e: Option<Zeroizing<D::Key>>,
+    pub rs: Option<D::Key>,
+    pub psk: Option<Zeroizing<[u8; PSK_LEN]>>,
+    /// Random seed for the `hfs` patterns: the initiator derives its KEM key
+    /// pair from it, the responder its encapsulation randomness.
+    pub kem_seed: Option<Zeroizing<[u8; 64]>>,
+}
+
+impl<D: Dh> Default for HandshakeKeys<D> {
+    fn default() -> Self {
+        Self {
+            s: None,
+            e: None,
+            rs: None,
+            psk: None,
+            kem_seed: None,
+        }
+    }
+}
+
+/// Runs any interactive pattern described by a `HandshakePattern`.
+///
+/// The local static key is any `StaticKey`, so it may live outside memory.
+pub struct HandshakeState<
+    C: Cipher = ChaChaPoly,
+    H: Hash = Blake2s,
+    D: Dh = X25519,
+    S: StaticKey<D> = SecretKey<D>,
+> {
+    pattern: &'static HandshakePattern,
+    initiator: bool,
+    s: Option<S>,
+    e: Option<Zeroizing<D::Key>>,
+    rs: Option<D::Key>,
+    re: Option<D::Key>,
+    psk: Option<Zeroizing<[u8; PSK_LEN]>>,
+    #[cfg(feature = "hybrid")]
+    kem_seed: Option<Zeroizing<[u8; kem::SEED_LEN]>>,
+    // Zeroed until `e1` is read. Not an `Option`, whose unused space could
+    // pick up stale stack bytes this large.
+    #[cfg(feature = "hybrid")]
+    re1: [u8; kem::PUBLIC_LEN],
+    #[cfg(feature = "hybrid")]
+    has_re1: bool,
+    pub(crate) c: SymmetricState<C, H>,
+    message: usize,
+}
+
+impl<C: Cipher, H: Hash, D: Dh> HandshakeState<C, H, D> {
+    pub fn new(
+        pattern: &'static HandshakePattern,
+        initiator: bool,
+        prologue: &[u8],
+        mut keys: HandshakeKeys<D>,
+    ) -> Result<Self, Error> {
+        let s = keys.s.take().map(SecretKey::from_zeroizing);
+        Self::with_static_key(pattern, initiator, prologue, s, keys)
+    }
+}
+
+impl<C: Cipher, H: Hash, D: Dh, S: StaticKey<D>> HandshakeState<C, H, D, S> {
+    /// Like `new`, with `s` as the local static key; `keys.s` is ignored.
+    pub fn with_static_key(
+        pattern: &'static HandshakePattern,
+        initiator: bool,
+        prologue: &[u8],
+        s: Option<S>,
+        keys: HandshakeKeys<D>,
+    ) -> Result<Self, Error> {
+        let mut state = Self {
+            pattern,
+            initiator,
+            s,
+            e: keys.e,
+            rs: keys.rs,
+            re: None,
+            psk: keys.psk,
+            #[cfg(feature = "hybrid")]
+            kem_seed: keys.kem_seed,
+            #[cfg(feature = "hybrid")]
+            re1: [0u8; kem::PUBLIC_LEN],
+            #[cfg(feature = "hybrid")]
+            has_re1: false,
+            c: SymmetricState::initialize(pattern.name, D::NAME, pattern.kem_name()),
+            message: 0,
+        };
+        state.c.mix_hash(prologue);
+
+        // pre-messages are hashed in order, the initiator's first
+        let pre = [
+            (initiator, pattern.initiator_pre),
+            (!initiator, pattern.responder_pre),
+        ];
+        for (local, tokens) in pre {
+            for token in tokens {
+                let key = match (token, local) {
+                    (Token::S, true) => state.s.as_ref().ok_or(Error::Input)?.public_key(),
+                    (Token::E, true) => D::pub_key(state.e.as_deref().ok_or(Error::Input)?),
+                    (Token::S, false) => state.rs.ok_or(Error::Input)?,
+                    _ => return Err(Error::Input),
+                };
+                state.c.mix_hash(key.as_ref());
+            }
+        }
+        Ok(state)
+    }
+
+    pub fn is_initiator(&self) -> bool {
+        self.initiator
+    }
+    pub fn is_finished(&self) -> bool {
+        self.message == self.pattern.messages.len()
+    }
+    /// Whether the next handshake message is ours to write.
+    pub fn is_my_turn(&self) -> bool {
+        !self.is_finished() && (self.message & 1 == 0) == self.initiator
+    }
+    /// Bytes that the next message adds on top of its payload.
+    pub fn overhead(&self) -> usize {
+        if self.is_finished() {
+            0
+        } else {
+            self.pattern.overhead_for(self.message, D::LEN)
+        }
+    }
+    pub fn remote_static(&self) -> Option<D::Key> {
+        self.rs
+    }
+    pub fn handshake_hash(&self) -> &[u8] {
+        self.c.hash()
+    }
+
+    pub fn write_message(&mut self, payload: &[u8], message: &mut [u8]) -> Result<usize, Error> {
+        if !self.is_my_turn() {
+            return Err(Error::Input);
+        }
+        let len = self.overhead() + payload.len();
+        if message.len() < len {
+            return Err(Error::Input);
+        }
+
+        let mut pos = 0;
+        for token in self.pattern.messages[self.message] {
+            match token {
+                Token::E => {
+                    let pub_e = D::pub_key(self.e.as_deref().ok_or(Error::Input)?);
+                    message[pos..pos + D::LEN].copy_from_slice(pub_e.as_ref());
+                    pos += D::LEN;
+                    self.c.mix_hash(pub_e.as_ref());
+                    if self.pattern.has_psk() {
+                        self.c.mix_key(pub_e.as_ref());
+                    }
+                }
+                Token::S => {
+                    let pub_s = self.s.as_ref().ok_or(Error::Input)?.public_key();
+                    pos += self
+                        .c
+                        .encrypt_and_hash(pub_s.as_ref(), &mut message[pos..])?;
+                }
+                #[cfg(feature = "hybrid")]
+                Token::E1 => {
+                    let pub_e1 = kem::public_key(self.kem_seed.as_deref().ok_or(Error::Input)?);
+                    pos += self.c.encrypt_and_hash(&pub_e1, &mut message[pos..])?;
+                }
+                #[cfg(feature = "hybrid")]
+                Token::Ekem1 => {
+                    if !self.has_re1 {
+                        return Err(Error::Input);
+                    }
+                    let seed = self.kem_seed.as_deref().ok_or(Error::Input)?;
+                    let (ct, shared) = kem::encapsulate(&self.re1, seed)?;
+                    pos += self.c.encrypt_and_hash(&ct, &mut message[pos..])?;
+                    self.c.mix_key(&shared[..]);
+                }
+                token => self.mix_token(*token)?,
+            }
+        }
+        pos += self.c.encrypt_and_hash(payload, &mut message[pos..])?;
+
+        self.message += 1;
+        Ok(pos)
+    }
+
+    pub fn read_message(&mut self, message: &[u8], payload: &mut [u8]) -> Result<usize, Error> {
+        if self.is_finished() || self.is_my_turn() {
+            return Err(Error::Input);
+        }
+        let overhead = self.overhead();
+        if message.len() < overhead || payload.len() < message.len() - overhead {
+            return Err(Error::Input);
+        }
+
+        let mut pos = 0;
+        for token in self.pattern.messages[self.message] {
+            match token {
+                Token::E => {
+                    let re = key::<D>(&message[pos..pos + D::LEN])?;
+                    pos += D::LEN;
+                    self.c.mix_hash(re.as_ref());
+                    if self.pattern.has_psk() {
+                        self.c.mix_key(re.as_ref());
+                    }
+                    self.re = Some(re);
+                }
+                Token::S => {
+                    let len = self.encrypted_len(D::LEN);
+                    let mut rs = [0u8; MAX_DH_LEN];
+                    self.c
+                        .decrypt_and_hash(&message[pos..pos + len], &mut rs[..D::LEN])?;
+                    pos += len;
+                    self.rs = Some(key::<D>(&rs[..D::LEN])?);
+                }
+                #[cfg(feature = "hybrid")]
+                Token::E1 => {
+                    let len = self.encrypted_len(kem::PUBLIC_LEN);
+                    self.c
+                        .decrypt_and_hash(&message[pos..pos + len], &mut self.re1)?;
+                    pos += len;
+                    self.has_re1 = true;
+                }
+                #[cfg(feature = "hybrid")]
+                Token::Ekem1 => {
+                    let len = self.encrypted_len(kem::CIPHERTEXT_LEN);
+                    let mut ct = [0u8; kem::CIPHERTEXT_LEN];
+                    self.c.decrypt_and_hash(&message[pos..pos + len], &mut ct)?;
+                    pos += len;
+                    let seed = self.kem_seed.as_deref().ok_or(Error::Input)?;
+                    let shared = kem::decapsulate(seed, &ct);
+                    self.c.mix_key(&shared[..]);
+                }
+                token => self.mix_token(*token)?,
+            }
+        }
+        let len = self
+            .c
+            .decrypt_and_hash(&message[pos..], &mut payload[..message.len() - overhead])?;
+
+        self.message += 1;
+        Ok(len)
+    }
+
+    fn encrypted_len(&self, len: usize) -> usize {
+        if self.c.has_key() {
+            len + crate::TAG_LEN
+        } else {
+            len
+        }
+    }
+
+    fn mix_token(&mut self, token: Token) -> Result<(), Error> {
+        let missing = || Error::Input;
+        let e = self.e.as_deref().ok_or_else(missing);
+        let s = self.s.as_ref().ok_or_else(missing);
+        let re = self.re.as_ref().ok_or_else(missing);
+        let rs = self.rs.as_ref().ok_or_else(missing);
+        let shared = match (token, self.initiator) {
+            (Token::EE, _) => D::dh(e?, re?)?,
+            (Token::ES, true) | (Token::SE, false) => D::dh(e?, rs?)?,
+            (Token::ES, false) | (Token::SE, true) => s?.dh(re?)?,
+            (Token::SS, _) => s?.dh(rs?)?,
+            (Token::Psk, _) => {
+                let psk = self.psk.as_deref().ok_or_else(missing)?;
+                self.c.mix_key_and_hash(psk);
+                return Ok(());
+            }
+            (Token::E, _) | (Token::S, _) => unreachable!(),
+            #[cfg(feature = "hybrid")]
+            (Token::E1, _) | (Token::Ekem1, _) => unreachable!(),
+        };
+        self.c.mix_key((*shared).as_ref());
+        Ok(())
+    }
+
+    pub fn into_transport(self) -> Result<Transport<C, D>, Error> {
+        if !self.is_finished() {
+            return Err(Error::Input);
+        }
+        let h = self.c.handshake_hash();
+        let (c1, c2) = self.c.split();
+        let (send, recv) = if self.initiator { (c1, c2) } else { (c2, c1) };
+        let rs = match self.rs {
+            Some(rs) => rs,
+            None => key::<D>(&[0u8; MAX_DH_LEN][..D::LEN])?,
+        };
+        Ok(Transport { rs, h, send, recv })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{pattern::*, AesGcm, Blake2b, Sha256, Sha512};
+    use alloc::vec::Vec;
+    use core::convert::TryFrom;
+    use serde_json::Value;
+    extern crate alloc;
+
+    fn pattern_snow(pattern: &'static HandshakePattern, ours_initiator: bool) {
+        let (init_s, init_e, resp_s, resp_e, psk) =
+            ([1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32], [5u8; 32]);
+        let (our_s, our_e, snow_s, snow_e) = if ours_initiator {
+            (init_s, init_e, resp_s, resp_e)
+        } else {
+            (resp_s, resp_e, init_s, init_e)
+        };
+        let (our_pre, snow_pre) = if ours_initiator {
+            (pattern.initiator_pre, pattern.responder_pre)
+        } else {
+            (pattern.responder_pre, pattern.initiator_pre)
+        };
+
+        let keys = HandshakeKeys {
+            s: Some(Zeroizing::new(our_s)),
+            e: Some(Zeroizing::new(our_e)),
+            rs: snow_pre
+                .contains(&Token::S)
+                .then(|| X25519::pub_key(&snow_s)),
+            psk: Some(Zeroizing::new(psk)),
+            ..HandshakeKeys::default()
+        };
+        let mut ours: HandshakeState =
+            HandshakeState::new(pattern, ours_initiator, &[], keys).unwrap();
+
+        let name = alloc::format!("Noise_{}_25519_ChaChaPoly_BLAKE2s", pattern.name);
+        let our_pub = X25519::pub_key(&our_s);
+        let mut builder = snow::Builder::new(name.parse().unwrap())
+            .local_private_key(&snow_s)
+            .fixed_ephemeral_key_for_testing_only(&snow_e);
+        if our_pre.contains(&Token::S) {
+            builder = builder.remote_public_key(&our_pub);
+        }
+        if let Some(location) = pattern.name.split("psk").nth(1) {
+            builder = builder.psk(location.parse().unwrap(), &psk);
+        }
+        let mut snow = if ours_initiator {
+            builder.build_responder().unwrap()
+        } else {
+            builder.build_initiator().unwrap()
+        };
+
+        let mut message = [0u8; 256];
+        let mut payload = [0u8; 256];
+        for i in 0..pattern.messages.len() {
+            let sent = [i as u8; 3];
+            if ours.is_my_turn() {
+                let len = ours.write_message(&sent, &mut message).unwrap();
+                assert_eq!(len, pattern.overhead(i) + sent.len());
+                let len = snow.read_message(&message[..len], &mut payload).unwrap();
+                assert_eq!(&payload[..len], &sent);
+            } else {
+                let len = snow.write_message(&sent, &mut message).unwrap();
+                let len = ours.read_message(&message[..len], &mut payload).unwrap();
+                assert_eq!(&payload[..len], &sent);
+            }
+        }
+        assert!(ours.is_finished());
+        assert_eq!(ours.handshake_hash(), snow.get_handshake_hash());
+
+        let mut ours = ours.into_transport().unwrap();
+        let mut snow = snow.into_transport_mode().unwrap();
+        let len = ours.write_message(b"ours", &mut message).unwrap();
+        let len = snow.read_message(&message[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"ours");
+        let len = snow.write_message(b"snow", &mut message).unwrap();
+        let len = ours.read_message(&message[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"snow");
+    }
+
+    #[test]
+    fn test_patterns_snow() {
+        let patterns = [
+            &NN, &NK, &NX, &XN, &XK, &XX, &KN, &KK, &KX, &IN, &IK, &IX, &IXPSK0, &IXPSK2,
+        ];
+        for pattern in patterns {
+            pattern_snow(pattern, true);
+            pattern_snow(pattern, false);
+        }
+    }
+
+    #[test]
+    fn test_out_of_turn() {
+        let keys = HandshakeKeys {
+            e: Some(Zeroizing::new([1u8; 32])),
+            ..HandshakeKeys::default()
+        };
+        let mut resp: HandshakeState = HandshakeState::new(&NN, false, &[], keys).unwrap();
+        assert!(matches!(
+            resp.write_message(&[], &mut [0u8; 64]),
+            Err(Error::Input)
+        ));
+        assert!(matches!(
+            HandshakeState::<ChaChaPoly, Blake2s>::new(&NK, true, &[], HandshakeKeys::default()),
+            Err(Error::Input)
+        ));
+    }
+
+    #[cfg(feature = "hybrid")]
+    fn hfs_states(pattern: &'static HandshakePattern) -> (HandshakeState, HandshakeState) {
+        let init_keys = HandshakeKeys {
+            s: Some(Zeroizing::new([1u8; 32])),
+            e: Some(Zeroizing::new([2u8; 32])),
+            rs: Some(X25519::pub_key(&[3u8; 32])),
+            kem_seed: Some(Zeroizing::new([6u8; 64])),
+            ..HandshakeKeys::default()
+        };
+        let resp_keys = HandshakeKeys {
+            s: Some(Zeroizing::new([3u8; 32])),
+            e: Some(Zeroizing::new([4u8; 32])),
+            kem_seed: Some(Zeroizing::new([7u8; 64])),
+            ..HandshakeKeys::default()
+        };
+        (
+            HandshakeState::new(pattern, true, &[], init_keys).unwrap(),
+            HandshakeState::new(pattern, false, &[], resp_keys).unwrap(),
+        )
+    }
+
+    #[cfg(feature = "hybrid")]
+    #[test]
+    fn test_hfs_roundtrip() {
+        for pattern in [&IXHFS, &IKHFS] {
+            let (mut init, mut resp) = hfs_states(pattern);
+            let mut message = [0u8; 2048];
+            let mut payload = [0u8; 2048];
+
+            let len = init.write_message(b"one", &mut message).unwrap();
+            assert_eq!(len, pattern.overhead(0) + 3);
+            let plen = resp.read_message(&message[..len], &mut payload).unwrap();
+            assert_eq!(&payload[..plen], b"one");
+
+            let len = resp.write_message(b"two", &mut message).unwrap();
+            assert_eq!(len, pattern.overhead(1) + 3);
+            let plen = init.read_message(&message[..len], &mut payload).unwrap();
+            assert_eq!(&payload[..plen], b"two");
+
+            assert_eq!(init.handshake_hash(), resp.handshake_hash());
+            assert_eq!(resp.remote_static(), Some(X25519::pub_key(&[1u8; 32])));
+            let mut init = init.into_transport().unwrap();
+            let mut resp = resp.into_transport().unwrap();
+            let len = init.write_message(b"hell no", &mut message).unwrap();
+            let plen = resp.read_message(&message[..len], &mut payload).unwrap();
+            assert_eq!(&payload[..plen], b"hell no");
+        }
+    }
+
+    #[cfg(feature = "hybrid")]
+    #[test]
+    fn test_hfs_tampered_kem() {
+        let (mut init, mut resp) = hfs_states(&IXHFS);
+        let mut message = [0u8; 2048];
+        let mut payload = [0u8; 2048];
+
+        let len = init.write_message(&[], &mut message).unwrap();
+        resp.read_message(&message[..len], &mut payload).unwrap();
+        let len = resp.write_message(&[], &mut message).unwrap();
+        // the first byte of the KEM ciphertext, right after `e`
+        message[X25519::LEN] ^= 1;
+        assert!(matches!(
+            init.read_message(&message[..len], &mut payload),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    // Published vectors from cacophony, limited to the patterns in `pattern`.
+    const VECTORS: &str = include_str!("../tests/vectors/cacophony.txt");
+
+    fn hex(value: &Value) -> Option<Vec<u8>> {
+        let s = value.as_str()?;
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+            .collect()
+    }
+
+    fn key<K: for<'a> TryFrom<&'a [u8]>>(value: &Value) -> Option<K> {
+        hex(value).and_then(|k| K::try_from(&k[..]).ok())
+    }
+
+    fn vector_state<C: Cipher, H: Hash, D: Dh>(
+        pattern: &'static HandshakePattern,
+        vector: &Value,
+        side: &str,
+    ) -> HandshakeState<C, H, D> {
+        let field = |name: &str| &vector[alloc::format!("{}_{}", side, name)];
+        let keys = HandshakeKeys {
+            s: key(field("static")).map(Zeroizing::new),
+            e: key(field("ephemeral")).map(Zeroizing::new),
+            rs: key(field("remote_static")),
+            psk: key(&field("psks")[0]).map(Zeroizing::new),
+            ..HandshakeKeys::default()
+        };
+        let prologue = hex(field("prologue")).unwrap();
+        HandshakeState::new(pattern, side == "init", &prologue, keys).unwrap()
+    }
+
+    fn run_vector<C: Cipher, H: Hash, D: Dh>(pattern: &'static HandshakePattern, vector: &Value) {
+        let name = vector["protocol_name"].as_str().unwrap();
+        let mut init = vector_state::<C, H, D>(pattern, vector, "init");
+        let mut resp = vector_state::<C, H, D>(pattern, vector, "resp");
+
+        let messages = vector["messages"].as_array().unwrap();
+        let mut message = [0u8; 512];
+        let mut payload = [0u8; 512];
+        let (handshake, transport) = messages.split_at(pattern.messages.len());
+
+        for (i, expected) in handshake.iter().enumerate() {
+            let (sender, receiver) = if i % 2 == 0 {
+                (&mut init, &mut resp)
+            } else {
+                (&mut resp, &mut init)
+            };
+            let sent = hex(&expected["payload"]).unwrap();
+            let len = sender.write_message(&sent, &mut message).unwrap();
+            assert_eq!(
+                message[..len],
+                hex(&expected["ciphertext"]).unwrap()[..],
+                "{}",
+                name
+            );
+            let len = receiver
+                .read_message(&message[..len], &mut payload)
+                .unwrap();
+            assert_eq!(payload[..len], sent[..], "{}", name);
+        }
+        let handshake_hash = hex(&vector["handshake_hash"]).unwrap();
+        assert_eq!(init.handshake_hash(), &handshake_hash[..], "{}", name);
+        assert_eq!(resp.handshake_hash(), &handshake_hash[..], "{}", name);
+
+        let mut init = init.into_transport().unwrap();
+        let mut resp = resp.into_transport().unwrap();
+        for (i, expected) in transport.iter().enumerate() {
+            let (sender, receiver) = if (handshake.len() + i) % 2 == 0 {
+                (&mut init, &mut resp)
+            } else {
+                (&mut resp, &mut init)
+            };
+            let sent = hex(&expected["payload"]).unwrap();
+            let len = sender.write_message(&sent, &mut message).unwrap();
+            assert_eq!(
+                message[..len],
+                hex(&expected["ciphertext"]).unwrap()[..],
+                "{}",
+                name
+            );
+            let len = receiver
+                .read_message(&message[..len], &mut payload)
+                .unwrap();
+            assert_eq!(payload[..len], sent[..], "{}", name);
+        }
+    }
+
+    fn run_suite<D: Dh>(pattern: &'static HandshakePattern, vector: &Value, suite: &[&str]) {
+        match (suite[0], suite[1]) {
+            ("ChaChaPoly", "BLAKE2s") => run_vector::<ChaChaPoly, Blake2s, D>(pattern, vector),
+            ("ChaChaPoly", "BLAKE2b") => run_vector::<ChaChaPoly, Blake2b, D>(pattern, vector),
+            ("ChaChaPoly", "SHA256") => run_vector::<ChaChaPoly, Sha256, D>(pattern, vector),
+            ("ChaChaPoly", "SHA512") => run_vector::<ChaChaPoly, Sha512, D>(pattern, vector),
+            ("AESGCM", "BLAKE2s") => run_vector::<AesGcm, Blake2s, D>(pattern, vector),
+            ("AESGCM", "BLAKE2b") => run_vector::<AesGcm, Blake2b, D>(pattern, vector),
+            ("AESGCM", "SHA256") => run_vector::<AesGcm, Sha256, D>(pattern, vector),
+            ("AESGCM", "SHA512") => run_vector::<AesGcm, Sha512, D>(pattern, vector),
+            _ => panic!("unknown suite {:?}", suite),
+        }
+    }
+
+    #[test]
+    fn test_cacophony_vectors() {
+        let patterns = [
+            &NN, &NK, &NX, &XN, &XK, &XX, &KN, &KK, &KX, &IN, &IK, &IX, &IXPSK2,
+        ];
+        let vectors: Value = serde_json::from_str(VECTORS).unwrap();
+        let vectors = vectors["vectors"].as_array().unwrap();
+        assert!(!vectors.is_empty());
+
+        for vector in vectors {
+            let name = vector["protocol_name"].as_str().unwrap();
+            let parts: Vec<&str> = name.split('_').collect();
+            let pattern = patterns.iter().find(|p| p.name == parts[1]).unwrap();
+            match parts[2] {
+                "25519" => run_suite::<X25519>(pattern, vector, &parts[3..]),
+                #[cfg(feature = "curve448")]
+                "448" => run_suite::<crate::X448>(pattern, vector, &parts[3..]),
+                #[cfg(not(feature = "curve448"))]
+                "448" => {}
+                _ => panic!("unknown suite {}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn test_static_key() {
+        // `StaticKeypair` only exposes its key to the handshake through
+        // `StaticKey`, like a key kept in a secure element would
+        let init_keys = HandshakeKeys {
+            e: Some(Zeroizing::new([2u8; 32])),
+            ..HandshakeKeys::default()
+        };
+        let resp_keys = HandshakeKeys {
+            s: Some(Zeroizing::new([3u8; 32])),
+            e: Some(Zeroizing::new([4u8; 32])),
+            ..HandshakeKeys::default()
+        };
+        let s = crate::StaticKeypair::from_secret([1u8; 32]);
+        let mut init: HandshakeState<ChaChaPoly, Blake2s, X25519, _> =
+            HandshakeState::with_static_key(&XX, true, &[], Some(s), init_keys).unwrap();
+        let mut resp: HandshakeState = HandshakeState::new(&XX, false, &[], resp_keys).unwrap();
+
+        let mut message = [0u8; 256];
+        let mut payload = [0u8; 256];
+        let len = init.write_message(&[], &mut message).unwrap();
+        resp.read_message(&message[..len], &mut payload).unwrap();
+        let len = resp.write_message(&[], &mut message).unwrap();
+        init.read_message(&message[..len], &mut payload).unwrap();
+        let len = init.write_message(&[], &mut message).unwrap();
+        resp.read_message(&message[..len], &mut payload).unwrap();
+
+        assert_eq!(init.handshake_hash(), resp.handshake_hash());
+        assert_eq!(resp.remote_static(), Some(X25519::pub_key(&[1u8; 32])));
+    }
+
+    #[cfg(feature = "curve448")]
+    #[test]
+    fn test_x448_transport() {
+        use crate::X448;
+
+        let init_keys = HandshakeKeys::<X448> {
+            s: Some(Zeroizing::new([1u8; 56])),
+            e: Some(Zeroizing::new([2u8; 56])),
+            ..HandshakeKeys::default()
+        };
+        let resp_keys = HandshakeKeys::<X448> {
+            s: Some(Zeroizing::new([3u8; 56])),
+            e: Some(Zeroizing::new([4u8; 56])),
+            ..HandshakeKeys::default()
+        };
+        let mut init: HandshakeState<ChaChaPoly, Blake2s, X448> =
+            HandshakeState::new(&IX, true, &[], init_keys).unwrap();
+        let mut resp: HandshakeState<ChaChaPoly, Blake2s, X448> =
+            HandshakeState::new(&IX, false, &[], resp_keys).unwrap();
+
+        let mut message = [0u8; 512];
+        let mut payload = [0u8; 512];
+        let len = init.write_message(&[], &mut message).unwrap();
+        resp.read_message(&message[..len], &mut payload).unwrap();
+        let len = resp.write_message(&[], &mut message).unwrap();
+        init.read_message(&message[..len], &mut payload).unwrap();
+
+        let init = init.into_transport().unwrap();
+        let resp = resp.into_transport().unwrap();
+        assert_eq!(init.remote_static(), X448::pub_key(&[3u8; 56]));
+        assert_eq!(resp.remote_static(), X448::pub_key(&[1u8; 56]));
+
+        // the full 56-byte key survives export and import
+        let mut blob = [0u8; 512];
+        let len = init.export(&[7u8; 32], 0, &mut blob).unwrap();
+        assert_eq!(len, Transport::<ChaChaPoly, X448>::export_len());
+        let init: Transport<ChaChaPoly, X448> =
+            Transport::import(&[7u8; 32], &blob[..len]).unwrap();
+        let (read, write) = init.split();
+        assert_eq!(read.remote_static(), X448::pub_key(&[3u8; 56]));
+        assert_eq!(write.remote_static(), X448::pub_key(&[3u8; 56]));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_read_message(
+            message in proptest::collection::vec(proptest::num::u8::ANY, 0..256),
+            payload_len in 0..256usize,
+        ) {
+            let mut payload = [0u8; 256];
+            for pattern in [&NN, &NK, &XX, &KK, &IK, &IX, &IXPSK2] {
+                let keys = HandshakeKeys {
+                    s: Some(Zeroizing::new([3u8; 32])),
+                    e: Some(Zeroizing::new([4u8; 32])),
+                    rs: Some(X25519::pub_key(&[1u8; 32])),
+                    psk: Some(Zeroizing::new([5u8; 32])),
+                    ..HandshakeKeys::default()
+                };
+                let mut resp: HandshakeState = HandshakeState::new(pattern, false, &[], keys).unwrap();
+                let _ = resp.read_message(&message, &mut payload[..payload_len]);
+            }
+        }
+    }
+}
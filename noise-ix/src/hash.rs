@@ -0,0 +1,82 @@
+use digest::Digest;
+use zeroize::{Zeroize, Zeroizing};
+
+pub(crate) const MAX_HASH_LEN: usize = 64;
+pub(crate) const MAX_BLOCK_LEN: usize = 128;
+
+/// Hash function of a Noise suite.
+pub trait Hash: Default {
+    const NAME: &'static str;
+    const LEN: usize;
+    const BLOCK_LEN: usize;
+
+    fn input(&mut self, data: &[u8]);
+    /// Writes the digest into `out[..Self::LEN]`.
+    fn result(self, out: &mut [u8]);
+}
+
+macro_rules! impl_hash {
+    ($name:ident, $digest:ty, $str:expr, $len:expr, $block_len:expr) => {
+        #[derive(Default)]
+        pub struct $name($digest);
+
+        impl Hash for $name {
+            const NAME: &'static str = $str;
+            const LEN: usize = $len;
+            const BLOCK_LEN: usize = $block_len;
+
+            fn input(&mut self, data: &[u8]) {
+                self.0.update(data);
+            }
+            fn result(self, out: &mut [u8]) {
+                out[..Self::LEN].copy_from_slice(&self.0.finalize());
+            }
+        }
+    };
+}
+
+impl_hash!(Blake2s, blake2::Blake2s, "BLAKE2s", 32, 64);
+impl_hash!(Blake2b, blake2::Blake2b, "BLAKE2b", 64, 128);
+impl_hash!(Sha256, sha2::Sha256, "SHA256", 32, 64);
+impl_hash!(Sha512, sha2::Sha512, "SHA512", 64, 128);
+
+pub(crate) fn hmac<H: Hash>(key: &[u8], data: &[&[u8]], out: &mut [u8]) {
+    let mut ipad = [0x36u8; MAX_BLOCK_LEN];
+    let mut opad = [0x5cu8; MAX_BLOCK_LEN];
+    for (i, k) in key.iter().enumerate() {
+        ipad[i] ^= k;
+        opad[i] ^= k;
+    }
+
+    let mut inner = H::default();
+    inner.input(&ipad[..H::BLOCK_LEN]);
+    for d in data {
+        inner.input(d);
+    }
+    let mut inner_hash = Zeroizing::new([0u8; MAX_HASH_LEN]);
+    inner.result(&mut *inner_hash);
+
+    let mut outer = H::default();
+    outer.input(&opad[..H::BLOCK_LEN]);
+    outer.input(&inner_hash[..H::LEN]);
+    outer.result(out);
+
+    ipad.zeroize();
+    opad.zeroize();
+}
+
+/// Noise `HKDF(chaining_key, input_key_material, num_outputs)`; the number of
+/// outputs is the number of slices in `outputs`.
+pub(crate) fn hkdf<H: Hash>(ck: &[u8], input_material: &[u8], outputs: &mut [&mut [u8]]) {
+    let mut temp_key = Zeroizing::new([0u8; MAX_HASH_LEN]);
+    hmac::<H>(ck, &[input_material], &mut *temp_key);
+
+    let mut prev = Zeroizing::new([0u8; MAX_HASH_LEN]);
+    let mut prev_len = 0;
+    for (i, output) in outputs.iter_mut().enumerate() {
+        let counter = [i as u8 + 1];
+        hmac::<H>(&temp_key[..H::LEN], &[&prev[..prev_len], &counter], output);
+        prev[..H::LEN].copy_from_slice(&output[..H::LEN]);
+        prev_len = H::LEN;
+    }
+}
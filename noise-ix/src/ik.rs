@@ -0,0 +1,364 @@
+//! Noise IK pattern.
+//!
+//! ```text
+//! <- s
+//! ...
+//! -> e, es, s, ss
+//! <- e, ee, se
+//! ```
+//!
+//! The initiator already knows the responder's static key, so the first
+//! payload is encrypted and the handshake fails against any other key.
+//!
+//! With the `hybrid` feature, `hybrid_initiator` and `hybrid_responder` run
+//! IKhfs, which adds the same ML-KEM exchange as IXhfs.
+
+use core::marker::PhantomData;
+
+#[cfg(feature = "hybrid")]
+use crate::Hfs;
+use crate::{
+    pattern::{self, HandshakePattern},
+    Blake2s, ChaChaPoly, Cipher, EphemeralKeypair, Error, HandshakeKeys, HandshakeState, Hash,
+    NoPsk, PublicKey, StaticKeypair, Transport,
+};
+
+/// Which IK variant the typestates below run.
+pub trait Mode {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern;
+}
+
+impl Mode for NoPsk {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern = &pattern::IK;
+}
+
+#[cfg(feature = "hybrid")]
+impl Mode for Hfs {
+    const HANDSHAKE_PATTERN: &'static HandshakePattern = &pattern::IKHFS;
+}
+
+pub fn initiator(
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    rs: PublicKey,
+    prologue: &[u8],
+) -> Initiator1 {
+    Initiator1::new(e, s, rs, prologue)
+}
+
+pub fn responder(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Responder1 {
+    Responder1::new(e, s, prologue)
+}
+
+#[cfg(feature = "hybrid")]
+pub fn hybrid_initiator(
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    rs: PublicKey,
+    prologue: &[u8],
+) -> Initiator1<Hfs> {
+    Initiator1::new(e, s, rs, prologue)
+}
+
+#[cfg(feature = "hybrid")]
+pub fn hybrid_responder(
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    prologue: &[u8],
+) -> Responder1<Hfs> {
+    Responder1::new(e, s, prologue)
+}
+
+pub struct Initiator1<M: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<M>,
+}
+
+pub struct Initiator2<M: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<M>,
+}
+
+pub struct Responder1<M: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<M>,
+}
+
+pub struct Responder2<M: Mode = NoPsk, C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+    mode: PhantomData<M>,
+}
+
+fn ik_state<M: Mode, C: Cipher, H: Hash>(
+    initiator: bool,
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    rs: Option<PublicKey>,
+    prologue: &[u8],
+) -> HandshakeState<C, H> {
+    #[cfg(feature = "hybrid")]
+    let kem_seed = Some(e.kem_seed());
+    #[cfg(not(feature = "hybrid"))]
+    let kem_seed = None;
+    let keys = HandshakeKeys {
+        s: Some(s.to_secret()),
+        e: Some(e.into_secret()),
+        rs: rs.map(|rs| *rs.as_bytes()),
+        kem_seed,
+        ..HandshakeKeys::default()
+    };
+    // the initiator always has `rs`, the only pre-message
+    HandshakeState::new(M::HANDSHAKE_PATTERN, initiator, prologue, keys).unwrap()
+}
+
+impl<M: Mode, C: Cipher, H: Hash> Initiator1<M, C, H> {
+    pub fn new(e: EphemeralKeypair, s: &StaticKeypair, rs: PublicKey, prologue: &[u8]) -> Self {
+        Self {
+            state: ik_state::<M, C, H>(true, e, s, Some(rs), prologue),
+            mode: PhantomData,
+        }
+    }
+
+    pub const fn overhead() -> usize {
+        M::HANDSHAKE_PATTERN.overhead(0)
+    }
+
+    pub fn write_message(
+        mut self,
+        payload: &[u8],
+        message: &mut [u8],
+    ) -> Result<(usize, Initiator2<M, C, H>), Error> {
+        let len = self.state.write_message(payload, message)?;
+        Ok((
+            len,
+            Initiator2 {
+                state: self.state,
+                mode: PhantomData,
+            },
+        ))
+    }
+}
+
+impl<M: Mode, C: Cipher, H: Hash> Responder1<M, C, H> {
+    pub fn new(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Self {
+        Self {
+            state: ik_state::<M, C, H>(false, e, s, None, prologue),
+            mode: PhantomData,
+        }
+    }
+
+    pub const fn overhead() -> usize {
+        M::HANDSHAKE_PATTERN.overhead(0)
+    }
+
+    pub fn read_message(
+        mut self,
+        message: &[u8],
+        payload: &mut [u8],
+    ) -> Result<(usize, Responder2<M, C, H>), Error> {
+        let len = self.state.read_message(message, payload)?;
+        Ok((
+            len,
+            Responder2 {
+                state: self.state,
+                mode: PhantomData,
+            },
+        ))
+    }
+}
+
+impl<M: Mode, C: Cipher, H: Hash> Responder2<M, C, H> {
+    pub const fn overhead() -> usize {
+        M::HANDSHAKE_PATTERN.overhead(1)
+    }
+    pub fn remote_key(&self) -> PublicKey {
+        PublicKey::from(self.state.remote_static().unwrap_or_default())
+    }
+    pub fn write_message(
+        mut self,
+        payload: &[u8],
+        message: &mut [u8],
+    ) -> Result<(usize, Transport<C>), Error> {
+        let len = self.state.write_message(payload, message)?;
+        Ok((len, self.state.into_transport()?))
+    }
+}
+
+impl<M: Mode, C: Cipher, H: Hash> Initiator2<M, C, H> {
+    pub const fn overhead() -> usize {
+        M::HANDSHAKE_PATTERN.overhead(1)
+    }
+    pub fn read_message(
+        mut self,
+        message: &[u8],
+        payload: &mut [u8],
+    ) -> Result<(usize, Transport<C>), Error> {
+        let len = self.state.read_message(message, payload)?;
+        Ok((len, self.state.into_transport()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dh, X25519};
+    extern crate alloc;
+
+    #[test]
+    fn test_ik_snow() {
+        let e = [0u8; 32];
+        let s = [1u8; 32];
+        let re = [2u8; 32];
+        let rs = [3u8; 32];
+
+        let mut snow_buf_init = [0u8; 200];
+        let mut snow_buf_resp = [0u8; 200];
+        let mut my_buf_init = [0u8; 200];
+        let mut my_buf_resp = [0u8; 200];
+
+        let prot_name = alloc::format!("Noise_{}_25519_ChaChaPoly_BLAKE2s", pattern::IK.name);
+        let mut snow_init = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&s)
+            .remote_public_key(&X25519::pub_key(&rs))
+            .fixed_ephemeral_key_for_testing_only(&e)
+            .build_initiator()
+            .unwrap();
+
+        let mut snow_resp = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&rs)
+            .fixed_ephemeral_key_for_testing_only(&re)
+            .build_responder()
+            .unwrap();
+        let my_init = initiator(
+            EphemeralKeypair::from_secret(e),
+            &StaticKeypair::from_secret(s),
+            PublicKey::from(X25519::pub_key(&rs)),
+            &[],
+        );
+        let my_resp = responder(
+            EphemeralKeypair::from_secret(re),
+            &StaticKeypair::from_secret(rs),
+            &[],
+        );
+
+        // -> e, es, s, ss
+        let len = snow_init
+            .write_message(b"0-rtt", &mut snow_buf_init)
+            .unwrap();
+        let (my_len, init) = my_init.write_message(b"0-rtt", &mut my_buf_init).unwrap();
+        assert_eq!(snow_buf_init[..len], my_buf_init[..my_len]);
+
+        snow_resp
+            .read_message(&my_buf_init[..len], &mut snow_buf_resp)
+            .unwrap();
+        let (plen, resp) = my_resp
+            .read_message(&snow_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..plen], b"0-rtt");
+        assert_eq!(resp.remote_key(), PublicKey::from(X25519::pub_key(&s)));
+
+        // <- e, ee, se
+        let len = snow_resp.write_message(b"ok", &mut snow_buf_resp).unwrap();
+        let (my_len, mut r_trans) = resp.write_message(b"ok", &mut my_buf_resp).unwrap();
+        assert_eq!(snow_buf_resp[..len], my_buf_resp[..my_len]);
+
+        snow_init
+            .read_message(&my_buf_resp[..len], &mut snow_buf_init)
+            .unwrap();
+        let (plen, mut i_trans) = init
+            .read_message(&snow_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..plen], b"ok");
+
+        assert_eq!(i_trans.handshake_hash(), snow_init.get_handshake_hash());
+        assert_eq!(r_trans.handshake_hash(), snow_resp.get_handshake_hash());
+
+        let mut snow_i_trans = snow_init.into_transport_mode().unwrap();
+        let mut snow_r_trans = snow_resp.into_transport_mode().unwrap();
+
+        let len = i_trans.write_message(b"hell no", &mut my_buf_init).unwrap();
+        let len = snow_r_trans
+            .read_message(&my_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..len], b"hell no");
+
+        let len = r_trans
+            .write_message(b"hell yes", &mut my_buf_resp)
+            .unwrap();
+        let len = snow_i_trans
+            .read_message(&my_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..len], b"hell yes");
+    }
+
+    #[test]
+    fn test_ik_wrong_key() {
+        let mut msg = [0u8; 200];
+        let mut payload = [0u8; 200];
+
+        let init = initiator(
+            EphemeralKeypair::from_secret([0u8; 32]),
+            &StaticKeypair::from_secret([1u8; 32]),
+            PublicKey::from(X25519::pub_key(&[4u8; 32])),
+            &[],
+        );
+        let resp = responder(
+            EphemeralKeypair::from_secret([2u8; 32]),
+            &StaticKeypair::from_secret([3u8; 32]),
+            &[],
+        );
+
+        let (len, _) = init.write_message(&[], &mut msg).unwrap();
+        assert!(matches!(
+            resp.read_message(&msg[..len], &mut payload),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[cfg(feature = "hybrid")]
+    #[test]
+    fn test_hfs_roundtrip() {
+        let mut msg = [0u8; 2048];
+        let mut payload = [0u8; 2048];
+        let s = StaticKeypair::from_secret([1u8; 32]);
+        let rs = StaticKeypair::from_secret([3u8; 32]);
+
+        let init = hybrid_initiator(
+            EphemeralKeypair::from_secret([0u8; 32]),
+            &s,
+            rs.public(),
+            &[],
+        );
+        let resp = hybrid_responder(EphemeralKeypair::from_secret([2u8; 32]), &rs, &[]);
+        assert_eq!(Initiator1::<Hfs>::overhead(), 32 + 1184 + 16 + 32 + 16 + 16);
+        assert_eq!(Responder2::<Hfs>::overhead(), 32 + 1088 + 16 + 16);
+
+        let (len, init) = init.write_message(b"0-rtt", &mut msg).unwrap();
+        assert_eq!(len, Initiator1::<Hfs>::overhead() + 5);
+        let (plen, resp) = resp.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..plen], b"0-rtt");
+        assert_eq!(resp.remote_key(), s.public());
+
+        let (len, mut r_trans) = resp.write_message(b"ok", &mut msg).unwrap();
+        let (plen, mut i_trans) = init.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..plen], b"ok");
+        assert_eq!(i_trans.remote_key(), rs.public());
+
+        let len = i_trans.write_message(b"hell no", &mut msg).unwrap();
+        let plen = r_trans.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..plen], b"hell no");
+
+        let init = hybrid_initiator(
+            EphemeralKeypair::from_secret([0u8; 32]),
+            &s,
+            PublicKey::from(X25519::pub_key(&[4u8; 32])),
+            &[],
+        );
+        let resp = hybrid_responder(EphemeralKeypair::from_secret([2u8; 32]), &rs, &[]);
+        let (len, _) = init.write_message(&[], &mut msg).unwrap();
+        assert!(matches!(
+            resp.read_message(&msg[..len], &mut payload),
+            Err(Error::Decrypt)
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+//! ML-KEM-768 for the hybrid `hfs` patterns.
+//!
+//! Like the X25519 keys, all randomness comes from the caller: a KEM key
+//! pair is derived from a 64-byte seed, and encapsulation uses the first
+//! 32 bytes of the responder's seed.
+
+use libcrux_ml_kem::mlkem768::{self, MlKem768Ciphertext, MlKem768PublicKey};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::Error;
+
+pub(crate) const NAME: &str = "+MLKEM768";
+pub(crate) const SEED_LEN: usize = 64;
+pub(crate) const PUBLIC_LEN: usize = 1184;
+pub(crate) const CIPHERTEXT_LEN: usize = 1088;
+
+pub(crate) fn public_key(seed: &[u8; SEED_LEN]) -> [u8; PUBLIC_LEN] {
+    let (mut private, public) = mlkem768::generate_key_pair(*seed).into_parts();
+    private[0..].zeroize();
+    public.into()
+}
+
+pub(crate) fn encapsulate(
+    public: &[u8; PUBLIC_LEN],
+    seed: &[u8; SEED_LEN],
+) -> Result<([u8; CIPHERTEXT_LEN], Zeroizing<[u8; 32]>), Error> {
+    let public = MlKem768PublicKey::from(public);
+    if !mlkem768::validate_public_key(&public) {
+        return Err(Error::Dh);
+    }
+    let mut m = Zeroizing::new([0u8; 32]);
+    m.copy_from_slice(&seed[..32]);
+    let (ct, shared) = mlkem768::encapsulate(&public, *m);
+    Ok((ct.into(), Zeroizing::new(shared)))
+}
+
+pub(crate) fn decapsulate(
+    seed: &[u8; SEED_LEN],
+    ciphertext: &[u8; CIPHERTEXT_LEN],
+) -> Zeroizing<[u8; 32]> {
+    let (mut private, _) = mlkem768::generate_key_pair(*seed).into_parts();
+    let shared = mlkem768::decapsulate(&private, &MlKem768Ciphertext::from(ciphertext));
+    private[0..].zeroize();
+    Zeroizing::new(shared)
+}
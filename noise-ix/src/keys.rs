@@ -0,0 +1,270 @@
+//! Typed X25519 keys.
+//!
+//! Secrets are wiped on drop. `PublicKey` compares in constant time and
+//! displays as lowercase hex; `PublicKey::base64` displays it as standard
+//! padded base64.
+
+use core::{convert::TryFrom, fmt};
+
+use rand_core::{CryptoRng, RngCore};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::{handshake::DH_LEN, Dh, Error, StaticKey, X25519};
+
+#[derive(Clone, Copy)]
+pub struct PublicKey([u8; DH_LEN]);
+
+/// A long-term identity key. Keep `secret()` somewhere safe to reuse it.
+pub struct StaticKeypair {
+    secret: Zeroizing<[u8; DH_LEN]>,
+    public: PublicKey,
+}
+
+/// A single-use key, consumed by the handshake that uses it. With the
+/// `hybrid` feature it also carries the seed of the ephemeral KEM key.
+pub struct EphemeralKeypair {
+    secret: Zeroizing<[u8; DH_LEN]>,
+    public: PublicKey,
+    #[cfg(feature = "hybrid")]
+    kem_seed: Zeroizing<[u8; crate::kem::SEED_LEN]>,
+}
+
+fn generate_secret<R: RngCore + CryptoRng>(rng: &mut R) -> Zeroizing<[u8; DH_LEN]> {
+    let mut secret = Zeroizing::new([0u8; DH_LEN]);
+    rng.fill_bytes(&mut secret[..]);
+    secret
+}
+
+impl PublicKey {
+    pub const fn from_bytes(bytes: [u8; DH_LEN]) -> Self {
+        Self(bytes)
+    }
+    pub fn as_bytes(&self) -> &[u8; DH_LEN] {
+        &self.0
+    }
+    pub fn base64(&self) -> Base64<'_> {
+        Base64(&self.0)
+    }
+}
+
+impl From<[u8; DH_LEN]> for PublicKey {
+    fn from(bytes: [u8; DH_LEN]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for PublicKey {
+    type Error = Error;
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = <[u8; DH_LEN]>::try_from(bytes).map_err(|_| Error::Input)?;
+        Ok(Self(bytes))
+    }
+}
+
+impl AsRef<[u8]> for PublicKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicKey({})", self)
+    }
+}
+
+/// Displays a key as standard padded base64.
+pub struct Base64<'a>(&'a [u8]);
+
+impl fmt::Display for Base64<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        for chunk in self.0.chunks(3) {
+            let mut group = [0u8; 3];
+            group[..chunk.len()].copy_from_slice(chunk);
+            let n = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+            for i in 0..4 {
+                let c = if i <= chunk.len() {
+                    ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]
+                } else {
+                    b'='
+                };
+                write!(f, "{}", c as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StaticKeypair {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self::from_secret(*generate_secret(rng))
+    }
+    pub fn from_secret(secret: [u8; DH_LEN]) -> Self {
+        let public = PublicKey(X25519::pub_key(&secret));
+        Self {
+            secret: Zeroizing::new(secret),
+            public,
+        }
+    }
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+    pub fn secret(&self) -> &[u8; DH_LEN] {
+        &self.secret
+    }
+    pub(crate) fn to_secret(&self) -> Zeroizing<[u8; DH_LEN]> {
+        self.secret.clone()
+    }
+}
+
+impl StaticKey<X25519> for StaticKeypair {
+    fn public_key(&self) -> [u8; DH_LEN] {
+        self.public.0
+    }
+    fn dh(&self, public: &[u8; DH_LEN]) -> Result<Zeroizing<[u8; DH_LEN]>, Error> {
+        X25519::dh(&self.secret, public)
+    }
+}
+
+impl fmt::Debug for StaticKeypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StaticKeypair({})", self.public)
+    }
+}
+
+impl EphemeralKeypair {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let keypair = Self::from_secret(*generate_secret(rng));
+        #[cfg(feature = "hybrid")]
+        let keypair = {
+            let mut kem_seed = Zeroizing::new([0u8; crate::kem::SEED_LEN]);
+            rng.fill_bytes(&mut kem_seed[..]);
+            Self {
+                kem_seed,
+                ..keypair
+            }
+        };
+        keypair
+    }
+    /// Fixed ephemeral keys are only safe for test vectors.
+    pub fn from_secret(secret: [u8; DH_LEN]) -> Self {
+        let public = PublicKey(X25519::pub_key(&secret));
+        Self {
+            secret: Zeroizing::new(secret),
+            public,
+            #[cfg(feature = "hybrid")]
+            kem_seed: {
+                use sha2::Digest;
+                let mut seed = Zeroizing::new([0u8; crate::kem::SEED_LEN]);
+                seed.copy_from_slice(&sha2::Sha512::digest(&secret));
+                seed
+            },
+        }
+    }
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+    pub(crate) fn into_secret(self) -> Zeroizing<[u8; DH_LEN]> {
+        self.secret
+    }
+    #[cfg(feature = "hybrid")]
+    pub(crate) fn kem_seed(&self) -> Zeroizing<[u8; crate::kem::SEED_LEN]> {
+        self.kem_seed.clone()
+    }
+}
+
+impl fmt::Debug for EphemeralKeypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EphemeralKeypair({})", self.public)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    extern crate alloc;
+    use alloc::string::ToString;
+
+    struct CountingRng(u8);
+
+    impl RngCore for CountingRng {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for b in dest {
+                *b = self.0;
+                self.0 = self.0.wrapping_add(1);
+            }
+        }
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for CountingRng {}
+
+    #[test]
+    fn test_generate() {
+        let mut rng = CountingRng(0);
+        let s = StaticKeypair::generate(&mut rng);
+        let e = EphemeralKeypair::generate(&mut rng);
+        let mut expected = [0u8; 32];
+        expected.iter_mut().zip(0..).for_each(|(b, i)| *b = i);
+        assert_eq!(s.secret(), &expected);
+        assert_eq!(s.public(), PublicKey::from(X25519::pub_key(&expected)));
+        assert_ne!(s.public(), e.public());
+    }
+
+    #[test]
+    fn test_display() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0xfb;
+        bytes[31] = 0x01;
+        let key = PublicKey::from(bytes);
+        assert_eq!(
+            key.to_string(),
+            "fb00000000000000000000000000000000000000000000000000000000000001"
+        );
+        assert_eq!(
+            key.base64().to_string(),
+            "+wAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAE="
+        );
+        assert_eq!(Base64(b"sha").to_string(), "c2hh");
+        assert_eq!(Base64(b"sh").to_string(), "c2g=");
+        assert_eq!(Base64(b"s").to_string(), "cw==");
+    }
+
+    #[test]
+    fn test_try_from() {
+        assert!(PublicKey::try_from(&[0u8; 31][..]).is_err());
+        assert_eq!(
+            PublicKey::try_from(&[7u8; 32][..]).unwrap(),
+            PublicKey::from([7u8; 32])
+        );
+    }
+}
@@ -1,35 +1,94 @@
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod cipher;
 mod cipher_state;
+mod datagram;
+mod dh;
 mod handshake;
+mod handshake_state;
+mod hash;
+pub mod ik;
+#[cfg(feature = "hybrid")]
+mod kem;
+mod keys;
+#[cfg(feature = "alloc")]
+mod owned;
+pub mod pattern;
+#[cfg(feature = "std")]
+mod stream;
 mod symmetric_state;
 mod transport;
-mod x25519;
+pub mod xx;
 
+pub use cipher::{AesGcm, ChaChaPoly, Cipher};
 use cipher_state::CipherState;
-use handshake::DH_LEN;
-pub use handshake::{Initiator1, Initiator2, Responder1, Responder2};
+pub use datagram::{DatagramTransport, NONCE_LEN, REPLAY_WINDOW};
+#[cfg(feature = "curve448")]
+pub use dh::X448;
+pub use dh::{Dh, SecretKey, StaticKey, X25519};
+#[cfg(feature = "hybrid")]
+pub use handshake::Hfs;
+use handshake::PSK_LEN;
+pub use handshake::{
+    Initiator1, Initiator2, Mode, NoPsk, Psk0, Psk2, PskMode, Responder1, Responder2, Unkeyed,
+};
+pub use handshake_state::{HandshakeKeys, HandshakeState};
+pub use hash::{Blake2b, Blake2s, Hash, Sha256, Sha512};
+pub use keys::{Base64, EphemeralKeypair, PublicKey, StaticKeypair};
+#[cfg(feature = "std")]
+pub use stream::{NoiseStream, DEFAULT_MAX_PAYLOAD_LEN, MAX_MESSAGE_LEN};
 use symmetric_state::SymmetricState;
 pub use transport::{NoiseRead, NoiseWrite, Transport};
 
 const TAG_LEN: usize = 16;
 
-pub fn initiator(e: [u8; DH_LEN], s: [u8; DH_LEN], prologue: &[u8]) -> Initiator1 {
-    let mut c = SymmetricState::new();
-    c.mix_hash(prologue);
-    Initiator1 { e, s, c }
+pub fn initiator(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Initiator1 {
+    Initiator1::new(e, s, prologue)
 }
 
-pub fn responder(e: [u8; DH_LEN], s: [u8; DH_LEN], prologue: &[u8]) -> Responder1 {
-    let mut c = SymmetricState::new();
-    c.mix_hash(prologue);
-    Responder1 {
-        e,
-        s,
-        re: [0u8; DH_LEN],
-        rs: [0u8; DH_LEN],
-        c,
-    }
+pub fn responder(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Responder1 {
+    Responder1::new(e, s, prologue)
+}
+
+pub fn initiator_psk<P: PskMode>(
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    psk: [u8; PSK_LEN],
+    prologue: &[u8],
+) -> Initiator1<P> {
+    Initiator1::with_psk(e, s, psk, prologue)
+}
+
+pub fn responder_psk<P: PskMode>(
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    psk: [u8; PSK_LEN],
+    prologue: &[u8],
+) -> Responder1<P> {
+    Responder1::with_psk(e, s, psk, prologue)
+}
+
+#[cfg(feature = "hybrid")]
+pub fn hybrid_initiator(
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    prologue: &[u8],
+) -> Initiator1<Hfs> {
+    Initiator1::new(e, s, prologue)
+}
+
+#[cfg(feature = "hybrid")]
+pub fn hybrid_responder(
+    e: EphemeralKeypair,
+    s: &StaticKeypair,
+    prologue: &[u8],
+) -> Responder1<Hfs> {
+    Responder1::new(e, s, prologue)
 }
 
 #[derive(Debug)]
@@ -37,8 +96,25 @@ pub enum Error {
     Input,
     Decrypt,
     Dh,
+    Nonce,
+    Replay,
 }
 
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Error::Input => "invalid input",
+            Error::Decrypt => "decryption failed",
+            Error::Dh => "invalid public key",
+            Error::Nonce => "nonce exhausted",
+            Error::Replay => "replayed message",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
 #[cfg(test)]
 mod test {
     use crate::*;
@@ -68,8 +144,16 @@ mod test {
             .fixed_ephemeral_key_for_testing_only(&re)
             .build_responder()
             .unwrap();
-        let my_init = initiator(e, s, &[]);
-        let my_resp = responder(re, rs, &[]);
+        let my_init = initiator(
+            EphemeralKeypair::from_secret(e),
+            &StaticKeypair::from_secret(s),
+            &[],
+        );
+        let my_resp = responder(
+            EphemeralKeypair::from_secret(re),
+            &StaticKeypair::from_secret(rs),
+            &[],
+        );
 
         let ilen = snow_init.write_message(&[], &mut snow_buf_init).unwrap();
         let (_, init) = my_init.write_message(&[], &mut my_buf_init).unwrap();
@@ -84,7 +168,7 @@ mod test {
 
         let _ = snow_resp.write_message(&[], &mut snow_buf_resp).unwrap();
         let mut snow_r_trans = snow_resp.into_transport_mode().unwrap();
-        let (len, r_trans) = resp.write_message(&[], &mut my_buf_resp).unwrap();
+        let (len, _r_trans) = resp.write_message(&[], &mut my_buf_resp).unwrap();
         assert_eq!(snow_buf_resp, my_buf_resp);
 
         let (_, mut i_trans) = init
@@ -93,17 +177,280 @@ mod test {
         snow_init
             .read_message(&snow_buf_resp[..len], &mut snow_buf_init)
             .unwrap();
+        let _snow_i_trans = snow_init.into_transport_mode().unwrap();
+
+        let len = i_trans.write_message(b"hell no", &mut my_buf_init).unwrap();
+        let len = snow_r_trans
+            .read_message(&my_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..len], b"hell no");
+        let len = i_trans.write_message(b"hell no", &mut my_buf_init).unwrap();
+        let len = snow_r_trans
+            .read_message(&my_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..len], b"hell no");
+    }
+
+    fn ixpsk_snow<P: PskMode>(psk_location: u8) {
+        let e = [0u8; 32];
+        let s = [1u8; 32];
+        let re = [2u8; 32];
+        let rs = [3u8; 32];
+        let psk = [4u8; 32];
+
+        let mut snow_buf_init = [0u8; 200];
+        let mut snow_buf_resp = [0u8; 200];
+        let mut my_buf_init = [0u8; 200];
+        let mut my_buf_resp = [0u8; 200];
+
+        let prot_name = alloc::format!("Noise_{}_25519_ChaChaPoly_BLAKE2s", P::PATTERN);
+        let mut snow_init = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&s)
+            .psk(psk_location, &psk)
+            .prologue(b"shas")
+            .fixed_ephemeral_key_for_testing_only(&e)
+            .build_initiator()
+            .unwrap();
+
+        let mut snow_resp = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&rs)
+            .psk(psk_location, &psk)
+            .prologue(b"shas")
+            .fixed_ephemeral_key_for_testing_only(&re)
+            .build_responder()
+            .unwrap();
+        let my_init = initiator_psk::<P>(
+            EphemeralKeypair::from_secret(e),
+            &StaticKeypair::from_secret(s),
+            psk,
+            b"shas",
+        );
+        let my_resp = responder_psk::<P>(
+            EphemeralKeypair::from_secret(re),
+            &StaticKeypair::from_secret(rs),
+            psk,
+            b"shas",
+        );
+
+        let len = snow_init.write_message(b"one", &mut snow_buf_init).unwrap();
+        let (my_len, init) = my_init.write_message(b"one", &mut my_buf_init).unwrap();
+        assert_eq!(snow_buf_init[..len], my_buf_init[..my_len]);
+
+        snow_resp
+            .read_message(&my_buf_init[..len], &mut snow_buf_resp)
+            .unwrap();
+        let (plen, resp) = my_resp
+            .read_message(&snow_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..plen], b"one");
+
+        let len = snow_resp.write_message(b"two", &mut snow_buf_resp).unwrap();
+        let (my_len, mut r_trans) = resp.write_message(b"two", &mut my_buf_resp).unwrap();
+        assert_eq!(snow_buf_resp[..len], my_buf_resp[..my_len]);
+
+        snow_init
+            .read_message(&my_buf_resp[..len], &mut snow_buf_init)
+            .unwrap();
+        let (plen, mut i_trans) = init
+            .read_message(&snow_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..plen], b"two");
+
         let mut snow_i_trans = snow_init.into_transport_mode().unwrap();
+        let mut snow_r_trans = snow_resp.into_transport_mode().unwrap();
 
         let len = i_trans.write_message(b"hell no", &mut my_buf_init).unwrap();
         let len = snow_r_trans
             .read_message(&my_buf_init[..len], &mut my_buf_resp)
             .unwrap();
-        assert_eq!(&my_buf_resp[..len], b"hell no".as_ref());
+        assert_eq!(&my_buf_resp[..len], b"hell no");
+
+        let len = r_trans
+            .write_message(b"hell yes", &mut my_buf_resp)
+            .unwrap();
+        let len = snow_i_trans
+            .read_message(&my_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..len], b"hell yes");
+    }
+
+    #[test]
+    fn test_ixpsk0_snow() {
+        ixpsk_snow::<Psk0>(0);
+    }
+
+    #[test]
+    fn test_ixpsk2_snow() {
+        ixpsk_snow::<Psk2>(2);
+    }
+
+    #[test]
+    fn test_ixpsk_wrong_psk() {
+        let mut msg = [0u8; 200];
+        let mut payload = [0u8; 200];
+
+        let init = initiator_psk::<Psk0>(
+            EphemeralKeypair::from_secret([0u8; 32]),
+            &StaticKeypair::from_secret([1u8; 32]),
+            [4u8; 32],
+            &[],
+        );
+        let resp = responder_psk::<Psk0>(
+            EphemeralKeypair::from_secret([2u8; 32]),
+            &StaticKeypair::from_secret([3u8; 32]),
+            [5u8; 32],
+            &[],
+        );
+        let (len, _) = init.write_message(&[], &mut msg).unwrap();
+        assert!(matches!(
+            resp.read_message(&msg[..len], &mut payload),
+            Err(Error::Decrypt)
+        ));
+
+        let init = initiator_psk::<Psk2>(
+            EphemeralKeypair::from_secret([0u8; 32]),
+            &StaticKeypair::from_secret([1u8; 32]),
+            [4u8; 32],
+            &[],
+        );
+        let resp = responder_psk::<Psk2>(
+            EphemeralKeypair::from_secret([2u8; 32]),
+            &StaticKeypair::from_secret([3u8; 32]),
+            [5u8; 32],
+            &[],
+        );
+        let (len, init) = init.write_message(&[], &mut msg).unwrap();
+        let (_, resp) = resp.read_message(&msg[..len], &mut payload).unwrap();
+        let (len, _) = resp.write_message(&[], &mut msg).unwrap();
+        assert!(matches!(
+            init.read_message(&msg[..len], &mut payload),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    fn ix_suite_snow<C: Cipher, H: Hash>() {
+        let e = [0u8; 32];
+        let s = [1u8; 32];
+        let re = [2u8; 32];
+        let rs = [3u8; 32];
+
+        let mut snow_buf_init = [0u8; 200];
+        let mut snow_buf_resp = [0u8; 200];
+        let mut my_buf_init = [0u8; 200];
+        let mut my_buf_resp = [0u8; 200];
+
+        let prot_name = alloc::format!("Noise_IX_25519_{}_{}", C::NAME, H::NAME);
+        let mut snow_init = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&s)
+            .fixed_ephemeral_key_for_testing_only(&e)
+            .build_initiator()
+            .unwrap();
+        let mut snow_resp = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&rs)
+            .fixed_ephemeral_key_for_testing_only(&re)
+            .build_responder()
+            .unwrap();
+        let my_init = Initiator1::<NoPsk, C, H>::new(
+            EphemeralKeypair::from_secret(e),
+            &StaticKeypair::from_secret(s),
+            &[],
+        );
+        let my_resp = Responder1::<NoPsk, C, H>::new(
+            EphemeralKeypair::from_secret(re),
+            &StaticKeypair::from_secret(rs),
+            &[],
+        );
+
+        let len = snow_init.write_message(b"one", &mut snow_buf_init).unwrap();
+        let (my_len, init) = my_init.write_message(b"one", &mut my_buf_init).unwrap();
+        assert_eq!(snow_buf_init[..len], my_buf_init[..my_len]);
+
+        snow_resp
+            .read_message(&my_buf_init[..len], &mut snow_buf_resp)
+            .unwrap();
+        let (_, resp) = my_resp
+            .read_message(&snow_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+
+        let len = snow_resp.write_message(b"two", &mut snow_buf_resp).unwrap();
+        let (my_len, mut r_trans) = resp.write_message(b"two", &mut my_buf_resp).unwrap();
+        assert_eq!(snow_buf_resp[..len], my_buf_resp[..my_len]);
+
+        snow_init
+            .read_message(&my_buf_resp[..len], &mut snow_buf_init)
+            .unwrap();
+        let (plen, mut i_trans) = init
+            .read_message(&snow_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..plen], b"two");
+
+        assert_eq!(i_trans.handshake_hash(), snow_init.get_handshake_hash());
+        assert_eq!(r_trans.handshake_hash(), snow_resp.get_handshake_hash());
+        assert_eq!(i_trans.handshake_hash().len(), H::LEN);
+
+        let mut snow_i_trans = snow_init.into_transport_mode().unwrap();
+        let mut snow_r_trans = snow_resp.into_transport_mode().unwrap();
+
         let len = i_trans.write_message(b"hell no", &mut my_buf_init).unwrap();
         let len = snow_r_trans
             .read_message(&my_buf_init[..len], &mut my_buf_resp)
             .unwrap();
-        assert_eq!(&my_buf_resp[..len], b"hell no".as_ref());
+        assert_eq!(&my_buf_resp[..len], b"hell no");
+
+        let (i_read, i_write) = i_trans.split();
+        assert_eq!(i_read.handshake_hash(), r_trans.handshake_hash());
+        assert_eq!(i_write.handshake_hash(), r_trans.handshake_hash());
+
+        r_trans.rekey_outgoing();
+        snow_i_trans.rekey_incoming();
+        let len = r_trans
+            .write_message(b"hell yes", &mut my_buf_resp)
+            .unwrap();
+        let len = snow_i_trans
+            .read_message(&my_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..len], b"hell yes");
+    }
+
+    #[test]
+    fn test_ix_suites_snow() {
+        ix_suite_snow::<ChaChaPoly, Blake2s>();
+        ix_suite_snow::<ChaChaPoly, Blake2b>();
+        ix_suite_snow::<ChaChaPoly, Sha256>();
+        ix_suite_snow::<ChaChaPoly, Sha512>();
+        ix_suite_snow::<AesGcm, Blake2s>();
+        ix_suite_snow::<AesGcm, Blake2b>();
+        ix_suite_snow::<AesGcm, Sha256>();
+        ix_suite_snow::<AesGcm, Sha512>();
+    }
+
+    #[cfg(feature = "hybrid")]
+    #[test]
+    fn test_hfs_roundtrip() {
+        let mut msg = [0u8; 2048];
+        let mut payload = [0u8; 2048];
+        let s = StaticKeypair::from_secret([1u8; 32]);
+        let rs = StaticKeypair::from_secret([3u8; 32]);
+
+        let init = hybrid_initiator(EphemeralKeypair::from_secret([0u8; 32]), &s, &[]);
+        let resp = hybrid_responder(EphemeralKeypair::from_secret([2u8; 32]), &rs, &[]);
+        assert_eq!(Initiator1::<Hfs>::overhead(), 32 + 1184 + 32);
+        assert_eq!(Responder2::<Hfs>::overhead(), 32 + 1088 + 16 + 48 + 16);
+
+        let (len, init) = init.write_message(b"one", &mut msg).unwrap();
+        assert_eq!(len, Initiator1::<Hfs>::overhead() + 3);
+        let (plen, resp) = resp.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..plen], b"one");
+        assert_eq!(resp.remote_key(), s.public());
+
+        let (len, mut r_trans) = resp.write_message(b"two", &mut msg).unwrap();
+        assert_eq!(len, Responder2::<Hfs>::overhead() + 3);
+        let (plen, mut i_trans) = init.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..plen], b"two");
+        assert_eq!(i_trans.remote_key(), rs.public());
+
+        let len = i_trans.write_message(b"hell no", &mut msg).unwrap();
+        let plen = r_trans.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..plen], b"hell no");
     }
 }
@@ -0,0 +1,246 @@
+//! `Vec` returning variants of every `write_message` and `read_message`.
+//!
+//! Outputs are sized from the `overhead()` of the message, so callers never
+//! guess buffer sizes.
+
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    ik, xx, Cipher, DatagramTransport, Dh, Error, HandshakeState, Hash, Initiator1, Initiator2,
+    Mode, NoiseRead, NoiseWrite, Responder1, Responder2, StaticKey, Transport,
+};
+
+/// The bytes of a handshake message and the state that follows it.
+type Step<T> = Result<(Vec<u8>, T), Error>;
+
+fn write_vec<T>(len: usize, write: impl FnOnce(&mut [u8]) -> Result<(usize, T), Error>) -> Step<T> {
+    let mut message = vec![0u8; len];
+    let (len, next) = write(&mut message)?;
+    message.truncate(len);
+    Ok((message, next))
+}
+
+fn read_vec<T>(
+    message: &[u8],
+    overhead: usize,
+    read: impl FnOnce(&mut [u8]) -> Result<(usize, T), Error>,
+) -> Step<T> {
+    if message.len() < overhead {
+        return Err(Error::Input);
+    }
+    write_vec(message.len() - overhead, read)
+}
+
+// For the transports, which write and read in place of moving to a new state.
+
+fn write_buf(
+    len: usize,
+    write: impl FnOnce(&mut [u8]) -> Result<usize, Error>,
+) -> Result<Vec<u8>, Error> {
+    write_vec(len, |message| Ok((write(message)?, ()))).map(|(message, ())| message)
+}
+
+fn read_buf(
+    message: &[u8],
+    overhead: usize,
+    read: impl FnOnce(&mut [u8]) -> Result<usize, Error>,
+) -> Result<Vec<u8>, Error> {
+    read_vec(message, overhead, |payload| Ok((read(payload)?, ()))).map(|(payload, ())| payload)
+}
+
+impl<C: Cipher, H: Hash, D: Dh, S: StaticKey<D>> HandshakeState<C, H, D, S> {
+    pub fn write_message_vec(&mut self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = self.overhead() + payload.len();
+        write_buf(len, |message| self.write_message(payload, message))
+    }
+    pub fn read_message_vec(&mut self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let overhead = self.overhead();
+        read_buf(message, overhead, |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<P: Mode, C: Cipher, H: Hash> Initiator1<P, C, H> {
+    pub fn write_message_vec(self, payload: &[u8]) -> Step<Initiator2<P, C, H>> {
+        write_vec(Self::overhead() + payload.len(), |message| {
+            self.write_message(payload, message)
+        })
+    }
+}
+
+impl<P: Mode, C: Cipher, H: Hash> Responder1<P, C, H> {
+    pub fn read_message_vec(self, message: &[u8]) -> Step<Responder2<P, C, H>> {
+        read_vec(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<P: Mode, C: Cipher, H: Hash> Responder2<P, C, H> {
+    pub fn write_message_vec(self, payload: &[u8]) -> Step<Transport<C>> {
+        write_vec(Self::overhead() + payload.len(), |message| {
+            self.write_message(payload, message)
+        })
+    }
+}
+
+impl<P: Mode, C: Cipher, H: Hash> Initiator2<P, C, H> {
+    pub fn read_message_vec(self, message: &[u8]) -> Step<Transport<C>> {
+        read_vec(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<M: ik::Mode, C: Cipher, H: Hash> ik::Initiator1<M, C, H> {
+    pub fn write_message_vec(self, payload: &[u8]) -> Step<ik::Initiator2<M, C, H>> {
+        write_vec(Self::overhead() + payload.len(), |message| {
+            self.write_message(payload, message)
+        })
+    }
+}
+
+impl<M: ik::Mode, C: Cipher, H: Hash> ik::Responder1<M, C, H> {
+    pub fn read_message_vec(self, message: &[u8]) -> Step<ik::Responder2<M, C, H>> {
+        read_vec(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<M: ik::Mode, C: Cipher, H: Hash> ik::Responder2<M, C, H> {
+    pub fn write_message_vec(self, payload: &[u8]) -> Step<Transport<C>> {
+        write_vec(Self::overhead() + payload.len(), |message| {
+            self.write_message(payload, message)
+        })
+    }
+}
+
+impl<M: ik::Mode, C: Cipher, H: Hash> ik::Initiator2<M, C, H> {
+    pub fn read_message_vec(self, message: &[u8]) -> Step<Transport<C>> {
+        read_vec(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<C: Cipher, H: Hash> xx::Initiator1<C, H> {
+    pub fn write_message_vec(self, payload: &[u8]) -> Step<xx::Initiator2<C, H>> {
+        write_vec(Self::overhead() + payload.len(), |message| {
+            self.write_message(payload, message)
+        })
+    }
+}
+
+impl<C: Cipher, H: Hash> xx::Responder1<C, H> {
+    pub fn read_message_vec(self, message: &[u8]) -> Step<xx::Responder2<C, H>> {
+        read_vec(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<C: Cipher, H: Hash> xx::Responder2<C, H> {
+    pub fn write_message_vec(self, payload: &[u8]) -> Step<xx::Responder3<C, H>> {
+        write_vec(Self::overhead() + payload.len(), |message| {
+            self.write_message(payload, message)
+        })
+    }
+}
+
+impl<C: Cipher, H: Hash> xx::Initiator2<C, H> {
+    pub fn read_message_vec(self, message: &[u8]) -> Step<xx::Initiator3<C, H>> {
+        read_vec(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<C: Cipher, H: Hash> xx::Initiator3<C, H> {
+    pub fn write_message_vec(self, payload: &[u8]) -> Step<Transport<C>> {
+        write_vec(Self::overhead() + payload.len(), |message| {
+            self.write_message(payload, message)
+        })
+    }
+}
+
+impl<C: Cipher, H: Hash> xx::Responder3<C, H> {
+    pub fn read_message_vec(self, message: &[u8]) -> Step<Transport<C>> {
+        read_vec(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<C: Cipher, D: Dh> Transport<C, D> {
+    pub fn write_message_vec(&mut self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = Self::overhead() + payload.len();
+        write_buf(len, |message| self.write_message(payload, message))
+    }
+    pub fn read_message_vec(&mut self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        read_buf(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<C: Cipher, D: Dh> NoiseWrite<C, D> {
+    pub fn write_message_vec(&mut self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = Self::overhead() + payload.len();
+        write_buf(len, |message| self.write_message(payload, message))
+    }
+}
+
+impl<C: Cipher, D: Dh> NoiseRead<C, D> {
+    pub fn read_message_vec(&mut self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        read_buf(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+impl<C: Cipher, D: Dh> DatagramTransport<C, D> {
+    pub fn write_message_vec(&mut self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = Self::overhead() + payload.len();
+        write_buf(len, |message| self.write_message(payload, message))
+    }
+    pub fn read_message_vec(&mut self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        read_buf(message, Self::overhead(), |payload| {
+            self.read_message(message, payload)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{EphemeralKeypair, StaticKeypair, TAG_LEN};
+
+    #[test]
+    fn test_ix_vec() {
+        let s = StaticKeypair::from_secret([1u8; 32]);
+        let rs = StaticKeypair::from_secret([3u8; 32]);
+        let init = crate::initiator(EphemeralKeypair::from_secret([0u8; 32]), &s, &[]);
+        let resp = crate::responder(EphemeralKeypair::from_secret([2u8; 32]), &rs, &[]);
+
+        let (message, init) = init.write_message_vec(b"one").unwrap();
+        assert_eq!(message.len(), Initiator1::<crate::NoPsk>::overhead() + 3);
+        let (payload, resp) = resp.read_message_vec(&message).unwrap();
+        assert_eq!(payload, b"one");
+        let (message, mut r_trans) = resp.write_message_vec(&[]).unwrap();
+        let (payload, i_trans) = init.read_message_vec(&message).unwrap();
+        assert!(payload.is_empty());
+
+        let (mut read, mut write) = i_trans.split();
+        let message = write.write_message_vec(b"hell no").unwrap();
+        assert_eq!(message.len(), TAG_LEN + 7);
+        assert_eq!(r_trans.read_message_vec(&message).unwrap(), b"hell no");
+        let message = r_trans.write_message_vec(&[]).unwrap();
+        assert!(read.read_message_vec(&message).unwrap().is_empty());
+        assert!(matches!(
+            read.read_message_vec(&message[..TAG_LEN - 1]),
+            Err(Error::Input)
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+//! Handshake pattern descriptions for `HandshakeState`.
+//!
+//! A pattern lists the pre-message tokens of each side and the tokens of
+//! every handshake message. Messages alternate, starting with the initiator.
+//!
+//! With the `hybrid` feature, the `hfs` patterns add an ML-KEM exchange to
+//! the ephemeral keys: `e1` sends a KEM public key and `ekem1` answers it
+//! with a ciphertext whose shared secret is mixed into the chaining key.
+
+#[cfg(feature = "hybrid")]
+use crate::kem;
+use crate::{handshake::DH_LEN, TAG_LEN};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    E,
+    S,
+    EE,
+    ES,
+    SE,
+    SS,
+    Psk,
+    #[cfg(feature = "hybrid")]
+    E1,
+    #[cfg(feature = "hybrid")]
+    Ekem1,
+}
+
+#[derive(Debug)]
+pub struct HandshakePattern {
+    /// Pattern part of the protocol name, including psk modifiers.
+    pub name: &'static str,
+    pub initiator_pre: &'static [Token],
+    pub responder_pre: &'static [Token],
+    pub messages: &'static [&'static [Token]],
+}
+
+use Token::*;
+
+// The helpers below are `const fn` so the typestates can use them for their
+// `overhead()` constants, hence the index loops.
+impl HandshakePattern {
+    const fn contains(&self, token: Token) -> bool {
+        let mut i = 0;
+        while i < self.messages.len() {
+            let mut j = 0;
+            while j < self.messages[i].len() {
+                if self.messages[i][j] as u8 == token as u8 {
+                    return true;
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        false
+    }
+
+    pub(crate) const fn has_psk(&self) -> bool {
+        self.contains(Psk)
+    }
+
+    /// KEM suffix of the DH part of the protocol name.
+    pub(crate) const fn kem_name(&self) -> &'static str {
+        #[cfg(feature = "hybrid")]
+        if self.contains(E1) {
+            return kem::NAME;
+        }
+        ""
+    }
+
+    /// Bytes that `message` adds on top of its payload with X25519.
+    pub const fn overhead(&self, message: usize) -> usize {
+        self.overhead_for(message, DH_LEN)
+    }
+
+    /// Bytes that `message` adds on top of its payload with `dh_len` byte
+    /// public keys.
+    pub const fn overhead_for(&self, message: usize, dh_len: usize) -> usize {
+        let has_psk = self.has_psk();
+        let mut has_key = false;
+        let mut len = 0;
+        let mut i = 0;
+        while i <= message {
+            let tokens = self.messages[i];
+            len = 0;
+            let mut j = 0;
+            while j < tokens.len() {
+                let tag = if has_key { TAG_LEN } else { 0 };
+                match tokens[j] {
+                    E => {
+                        len += dh_len;
+                        has_key |= has_psk;
+                    }
+                    S => len += dh_len + tag,
+                    #[cfg(feature = "hybrid")]
+                    E1 => len += kem::PUBLIC_LEN + tag,
+                    #[cfg(feature = "hybrid")]
+                    Ekem1 => {
+                        len += kem::CIPHERTEXT_LEN + tag;
+                        has_key = true;
+                    }
+                    _ => has_key = true,
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        len + if has_key { TAG_LEN } else { 0 }
+    }
+}
+
+pub const NN: HandshakePattern = HandshakePattern {
+    name: "NN",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E], &[E, EE]],
+};
+
+pub const NK: HandshakePattern = HandshakePattern {
+    name: "NK",
+    initiator_pre: &[],
+    responder_pre: &[S],
+    messages: &[&[E, ES], &[E, EE]],
+};
+
+pub const NX: HandshakePattern = HandshakePattern {
+    name: "NX",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E], &[E, EE, S, ES]],
+};
+
+pub const XN: HandshakePattern = HandshakePattern {
+    name: "XN",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E], &[E, EE], &[S, SE]],
+};
+
+pub const XK: HandshakePattern = HandshakePattern {
+    name: "XK",
+    initiator_pre: &[],
+    responder_pre: &[S],
+    messages: &[&[E, ES], &[E, EE], &[S, SE]],
+};
+
+pub const XX: HandshakePattern = HandshakePattern {
+    name: "XX",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E], &[E, EE, S, ES], &[S, SE]],
+};
+
+pub const KN: HandshakePattern = HandshakePattern {
+    name: "KN",
+    initiator_pre: &[S],
+    responder_pre: &[],
+    messages: &[&[E], &[E, EE, SE]],
+};
+
+pub const KK: HandshakePattern = HandshakePattern {
+    name: "KK",
+    initiator_pre: &[S],
+    responder_pre: &[S],
+    messages: &[&[E, ES, SS], &[E, EE, SE]],
+};
+
+pub const KX: HandshakePattern = HandshakePattern {
+    name: "KX",
+    initiator_pre: &[S],
+    responder_pre: &[],
+    messages: &[&[E], &[E, EE, SE, S, ES]],
+};
+
+pub const IN: HandshakePattern = HandshakePattern {
+    name: "IN",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E, S], &[E, EE, SE]],
+};
+
+pub const IK: HandshakePattern = HandshakePattern {
+    name: "IK",
+    initiator_pre: &[],
+    responder_pre: &[S],
+    messages: &[&[E, ES, S, SS], &[E, EE, SE]],
+};
+
+pub const IX: HandshakePattern = HandshakePattern {
+    name: "IX",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E, S], &[E, EE, SE, S, ES]],
+};
+
+pub const IXPSK0: HandshakePattern = HandshakePattern {
+    name: "IXpsk0",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[Psk, E, S], &[E, EE, SE, S, ES]],
+};
+
+pub const IXPSK2: HandshakePattern = HandshakePattern {
+    name: "IXpsk2",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E, S], &[E, EE, SE, S, ES, Psk]],
+};
+
+#[cfg(feature = "hybrid")]
+pub const IXHFS: HandshakePattern = HandshakePattern {
+    name: "IXhfs",
+    initiator_pre: &[],
+    responder_pre: &[],
+    messages: &[&[E, E1, S], &[E, EE, Ekem1, SE, S, ES]],
+};
+
+#[cfg(feature = "hybrid")]
+pub const IKHFS: HandshakePattern = HandshakePattern {
+    name: "IKhfs",
+    initiator_pre: &[],
+    responder_pre: &[S],
+    messages: &[&[E, ES, E1, S, SS], &[E, EE, Ekem1, SE]],
+};
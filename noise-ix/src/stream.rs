@@ -0,0 +1,227 @@
+//! Noise IX over any `AsyncRead + AsyncWrite` byte stream.
+//!
+//! Every Noise message goes on the wire behind its 2-byte big-endian length.
+//! A payload is sent as its 4-byte big-endian length followed by its bytes,
+//! split over as many Noise messages as it needs, so payloads are not bound
+//! by `MAX_MESSAGE_LEN`. A received payload longer than the stream's maximum,
+//! `DEFAULT_MAX_PAYLOAD_LEN` unless set otherwise, is refused as soon as its
+//! length is read.
+
+use core::convert::{TryFrom, TryInto};
+use std::{io, vec, vec::Vec};
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{EphemeralKeypair, Error, PublicKey, StaticKeypair, Transport, TAG_LEN};
+
+pub const MAX_MESSAGE_LEN: usize = 65535;
+pub const DEFAULT_MAX_PAYLOAD_LEN: usize = 1 << 20;
+const MAX_CHUNK_LEN: usize = MAX_MESSAGE_LEN - TAG_LEN;
+const LEN_PREFIX: usize = 4;
+
+pub struct NoiseStream<S> {
+    stream: S,
+    transport: Transport,
+    message: Vec<u8>,
+    max_payload_len: usize,
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let len = u16::from_be_bytes(len) as usize;
+    stream.read_exact(&mut buf[..len]).await?;
+    Ok(len)
+}
+
+async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, message: &[u8]) -> io::Result<()> {
+    stream
+        .write_all(&(message.len() as u16).to_be_bytes())
+        .await?;
+    stream.write_all(message).await
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> NoiseStream<S> {
+    pub async fn initiate(
+        mut stream: S,
+        e: EphemeralKeypair,
+        s: &StaticKeypair,
+        prologue: &[u8],
+    ) -> io::Result<Self> {
+        let mut message = vec![0u8; MAX_MESSAGE_LEN];
+        let mut payload = vec![0u8; MAX_MESSAGE_LEN];
+
+        let (len, init) = crate::initiator(e, s, prologue).write_message(&[], &mut message)?;
+        write_frame(&mut stream, &message[..len]).await?;
+        stream.flush().await?;
+
+        let len = read_frame(&mut stream, &mut message).await?;
+        let (_, transport) = init.read_message(&message[..len], &mut payload)?;
+        Ok(Self {
+            stream,
+            transport,
+            message,
+            max_payload_len: DEFAULT_MAX_PAYLOAD_LEN,
+        })
+    }
+
+    pub async fn accept(
+        mut stream: S,
+        e: EphemeralKeypair,
+        s: &StaticKeypair,
+        prologue: &[u8],
+    ) -> io::Result<Self> {
+        let mut message = vec![0u8; MAX_MESSAGE_LEN];
+        let mut payload = vec![0u8; MAX_MESSAGE_LEN];
+
+        let len = read_frame(&mut stream, &mut message).await?;
+        let (_, resp) =
+            crate::responder(e, s, prologue).read_message(&message[..len], &mut payload)?;
+
+        let (len, transport) = resp.write_message(&[], &mut message)?;
+        write_frame(&mut stream, &message[..len]).await?;
+        stream.flush().await?;
+        Ok(Self {
+            stream,
+            transport,
+            message,
+            max_payload_len: DEFAULT_MAX_PAYLOAD_LEN,
+        })
+    }
+
+    pub fn remote_key(&self) -> PublicKey {
+        self.transport.remote_key()
+    }
+
+    pub fn handshake_hash(&self) -> &[u8] {
+        self.transport.handshake_hash()
+    }
+
+    /// Sets the longest payload `recv` accepts.
+    pub fn set_max_payload_len(&mut self, len: usize) {
+        self.max_payload_len = len;
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    pub async fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        let total = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "payload too large"))?;
+
+        let mut chunk = Vec::with_capacity(MAX_CHUNK_LEN);
+        chunk.extend_from_slice(&total.to_be_bytes());
+        let mut rest = payload;
+        loop {
+            let take = rest.len().min(MAX_CHUNK_LEN - chunk.len());
+            chunk.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+
+            let len = self.transport.write_message(&chunk, &mut self.message)?;
+            write_frame(&mut self.stream, &self.message[..len]).await?;
+            chunk.clear();
+            if rest.is_empty() {
+                break;
+            }
+        }
+        self.stream.flush().await
+    }
+
+    pub async fn recv(&mut self) -> io::Result<Vec<u8>> {
+        let mut payload = Vec::new();
+        let mut total = None;
+        loop {
+            let len = read_frame(&mut self.stream, &mut self.message).await?;
+            let start = payload.len();
+            payload.resize(start + len.saturating_sub(TAG_LEN), 0);
+            let read = self
+                .transport
+                .read_message(&self.message[..len], &mut payload[start..])?;
+            payload.truncate(start + read);
+
+            let total = match total {
+                Some(total) => total,
+                None if payload.len() >= LEN_PREFIX => {
+                    let prefix = payload.drain(..LEN_PREFIX).collect::<Vec<_>>();
+                    let len = u32::from_be_bytes(prefix[..].try_into().unwrap()) as usize;
+                    if len > self.max_payload_len {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "payload too large",
+                        ));
+                    }
+                    *total.insert(len)
+                }
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, "short chunk")),
+            };
+            if payload.len() > total {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk overrun"));
+            }
+            if payload.len() == total {
+                return Ok(payload);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_std::os::unix::net::UnixStream;
+
+    async fn streams() -> (NoiseStream<UnixStream>, NoiseStream<UnixStream>) {
+        let (a, b) = UnixStream::pair().unwrap();
+        let s = StaticKeypair::from_secret([1u8; 32]);
+        let rs = StaticKeypair::from_secret([3u8; 32]);
+        let (init, resp) = futures::join!(
+            NoiseStream::initiate(a, EphemeralKeypair::from_secret([0u8; 32]), &s, &[]),
+            NoiseStream::accept(b, EphemeralKeypair::from_secret([2u8; 32]), &rs, &[]),
+        );
+        let (init, resp) = (init.unwrap(), resp.unwrap());
+        assert_eq!(init.remote_key(), rs.public());
+        assert_eq!(resp.remote_key(), s.public());
+        assert_eq!(init.handshake_hash(), resp.handshake_hash());
+        (init, resp)
+    }
+
+    #[async_std::test]
+    async fn test_stream() {
+        let (mut init, mut resp) = streams().await;
+
+        init.send(b"hell no").await.unwrap();
+        assert_eq!(resp.recv().await.unwrap(), b"hell no");
+        resp.send(&[]).await.unwrap();
+        assert_eq!(init.recv().await.unwrap(), b"");
+    }
+
+    #[async_std::test]
+    async fn test_stream_chunked() {
+        let (mut init, mut resp) = streams().await;
+
+        let big: Vec<u8> = (0..3 * MAX_MESSAGE_LEN).map(|i| i as u8).collect();
+        let (sent, received) = futures::join!(init.send(&big), resp.recv());
+        sent.unwrap();
+        assert_eq!(received.unwrap(), big);
+    }
+
+    #[async_std::test]
+    async fn test_stream_too_large() {
+        let (mut init, mut resp) = streams().await;
+        resp.set_max_payload_len(100);
+
+        init.send(&[0u8; 100]).await.unwrap();
+        assert_eq!(resp.recv().await.unwrap().len(), 100);
+
+        // refused on its first chunk, before the rest is read
+        init.send(&[0u8; MAX_MESSAGE_LEN]).await.unwrap();
+        let e = resp.recv().await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,44 +1,106 @@
-use core::convert::TryInto;
+use core::marker::PhantomData;
 
-use crate::cipher_state::CipherState;
-use blake2::{Blake2s, Digest};
-use hkdf::Hkdf;
+use zeroize::Zeroizing;
 
-//const PROT_NAME: &str = "Noise_IX_25519_ChaChaPoly_BLAKE2s";
-const PRE_H_NAME: [u8; 32] = [
-    136, 89, 115, 104, 170, 155, 207, 183, 46, 236, 244, 45, 60, 201, 126, 60, 101, 209, 91, 111,
-    253, 217, 79, 89, 91, 17, 252, 97, 201, 249, 0, 193,
-];
+use crate::{
+    cipher::{Cipher, KEY_LEN},
+    cipher_state::CipherState,
+    hash::{hkdf, Hash, MAX_HASH_LEN},
+};
 
-pub(crate) struct SymmetricState {
-    ck: [u8; 32],
-    h: [u8; 32],
-    cipher: CipherState,
+#[derive(Clone, Copy)]
+pub(crate) struct HandshakeHash {
+    h: [u8; MAX_HASH_LEN],
+    len: usize,
+}
+
+impl HandshakeHash {
+    pub(crate) const STATE_LEN: usize = 1 + MAX_HASH_LEN;
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.h[..self.len]
+    }
+    pub(crate) fn import(state: &[u8]) -> Result<Self, crate::Error> {
+        let len = state[0] as usize;
+        if len > MAX_HASH_LEN {
+            return Err(crate::Error::Input);
+        }
+        let mut h = [0u8; MAX_HASH_LEN];
+        h.copy_from_slice(&state[1..Self::STATE_LEN]);
+        Ok(Self { h, len })
+    }
+    pub(crate) fn export(&self, state: &mut [u8]) {
+        state[0] = self.len as u8;
+        state[1..Self::STATE_LEN].copy_from_slice(&self.h);
+    }
+}
+
+pub(crate) struct SymmetricState<C: Cipher, H: Hash> {
+    ck: Zeroizing<[u8; MAX_HASH_LEN]>,
+    h: [u8; MAX_HASH_LEN],
+    cipher: CipherState<C>,
     has_key: bool,
+    hash: PhantomData<H>,
 }
 
-impl SymmetricState {
-    pub(crate) fn new() -> Self {
+impl<C: Cipher, H: Hash> SymmetricState<C, H> {
+    pub(crate) fn initialize(pattern: &str, dh: &str, kem: &str) -> Self {
+        let protocol_name = ["Noise_", pattern, "_", dh, kem, "_", C::NAME, "_", H::NAME];
+        let name_len: usize = protocol_name.iter().map(|part| part.len()).sum();
+
+        let mut h = [0u8; MAX_HASH_LEN];
+        if name_len <= H::LEN {
+            let mut pos = 0;
+            for part in &protocol_name {
+                h[pos..pos + part.len()].copy_from_slice(part.as_bytes());
+                pos += part.len();
+            }
+        } else {
+            let mut hash = H::default();
+            for part in &protocol_name {
+                hash.input(part.as_bytes());
+            }
+            hash.result(&mut h);
+        }
         Self {
-            ck: PRE_H_NAME,
-            h: PRE_H_NAME,
-            cipher: CipherState::new([0u8; 32]),
+            ck: Zeroizing::new(h),
+            h,
+            cipher: CipherState::new(&[0u8; KEY_LEN]),
             has_key: false,
+            hash: PhantomData,
         }
     }
     pub(crate) fn mix_key(&mut self, input_material: &[u8]) {
-        let hkdf = Hkdf::<Blake2s>::new(Some(&self.ck), input_material);
-        let mut output = [0u8; 64];
-        hkdf.expand(&[], &mut output).unwrap();
-        self.ck.copy_from_slice(&output[..32]);
-        self.cipher = CipherState::new(output[32..].try_into().unwrap());
+        let mut ck = Zeroizing::new([0u8; MAX_HASH_LEN]);
+        let mut temp_k = Zeroizing::new([0u8; MAX_HASH_LEN]);
+        hkdf::<H>(
+            &self.ck[..H::LEN],
+            input_material,
+            &mut [&mut ck[..], &mut temp_k[..]],
+        );
+        self.ck = ck;
+        self.cipher = CipherState::new(&temp_k[..KEY_LEN]);
+        self.has_key = true;
+    }
+    pub(crate) fn mix_key_and_hash(&mut self, input_material: &[u8]) {
+        let mut ck = Zeroizing::new([0u8; MAX_HASH_LEN]);
+        let mut temp_h = [0u8; MAX_HASH_LEN];
+        let mut temp_k = Zeroizing::new([0u8; MAX_HASH_LEN]);
+        hkdf::<H>(
+            &self.ck[..H::LEN],
+            input_material,
+            &mut [&mut ck[..], &mut temp_h[..], &mut temp_k[..]],
+        );
+        self.ck = ck;
+        self.mix_hash(&temp_h[..H::LEN]);
+        self.cipher = CipherState::new(&temp_k[..KEY_LEN]);
         self.has_key = true;
     }
     pub(crate) fn mix_hash(&mut self, data: &[u8]) {
-        let mut hash = Blake2s::new();
-        hash.update(&self.h);
-        hash.update(data);
-        self.h = hash.finalize().into();
+        let mut hash = H::default();
+        hash.input(&self.h[..H::LEN]);
+        hash.input(data);
+        hash.result(&mut self.h);
     }
     pub(crate) fn encrypt_and_hash(
         &mut self,
@@ -46,7 +108,8 @@ impl SymmetricState {
         message: &mut [u8],
     ) -> Result<usize, crate::Error> {
         let len = if self.has_key {
-            self.cipher.encrypt_with_ad(&self.h, payload, message)?
+            self.cipher
+                .encrypt_with_ad(&self.h[..H::LEN], payload, message)?
         } else {
             if message.len() < payload.len() {
                 return Err(crate::Error::Input);
@@ -64,8 +127,12 @@ impl SymmetricState {
         payload: &mut [u8],
     ) -> Result<usize, crate::Error> {
         let len = if self.has_key {
-            self.cipher.decrypt_with_ad(&self.h, message, payload)?
+            self.cipher
+                .decrypt_with_ad(&self.h[..H::LEN], message, payload)?
         } else {
+            if payload.len() < message.len() {
+                return Err(crate::Error::Input);
+            }
             let (payload, _) = payload.split_at_mut(message.len());
             payload.copy_from_slice(message);
             message.len()
@@ -74,13 +141,36 @@ impl SymmetricState {
         Ok(len)
     }
 
-    pub(crate) fn split(self) -> (CipherState, CipherState) {
-        let hkdf = Hkdf::<Blake2s>::new(Some(&self.ck), &[]);
-        let mut output = [0u8; 64];
-        hkdf.expand(&[], &mut output).unwrap();
+    pub(crate) fn has_key(&self) -> bool {
+        self.has_key
+    }
+    pub(crate) fn hash(&self) -> &[u8] {
+        &self.h[..H::LEN]
+    }
+
+    pub(crate) fn handshake_hash(&self) -> HandshakeHash {
+        HandshakeHash {
+            h: self.h,
+            len: H::LEN,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn keys(&self) -> ([u8; MAX_HASH_LEN], [u8; KEY_LEN]) {
+        (*self.ck, self.cipher.key())
+    }
+
+    pub(crate) fn split(self) -> (CipherState<C>, CipherState<C>) {
+        let mut temp_k1 = Zeroizing::new([0u8; MAX_HASH_LEN]);
+        let mut temp_k2 = Zeroizing::new([0u8; MAX_HASH_LEN]);
+        hkdf::<H>(
+            &self.ck[..H::LEN],
+            &[],
+            &mut [&mut temp_k1[..], &mut temp_k2[..]],
+        );
         (
-            CipherState::new(output[..32].try_into().unwrap()),
-            CipherState::new(output[32..].try_into().unwrap()),
+            CipherState::new(&temp_k1[..KEY_LEN]),
+            CipherState::new(&temp_k2[..KEY_LEN]),
         )
     }
 }
@@ -1,25 +1,68 @@
-use crate::{CipherState, Error};
+use core::convert::TryInto;
 
-pub struct Transport {
-    pub(crate) rs: [u8; 32],
-    pub(crate) send: CipherState,
-    pub(crate) recv: CipherState,
+use zeroize::Zeroize;
+
+use crate::{
+    cipher_state::{self, CipherState},
+    dh::{key, MAX_DH_LEN},
+    symmetric_state::HandshakeHash,
+    ChaChaPoly, Cipher, Dh, Error, PublicKey, TAG_LEN, X25519,
+};
+
+const SEAL_NONCE_LEN: usize = 8;
+const MAX_FULL_STATE_LEN: usize = full_state_len(MAX_DH_LEN);
+
+// An exported state is the remote static key and the handshake hash,
+// followed by one cipher state for a half and two for a whole transport.
+const fn header_len(dh_len: usize) -> usize {
+    dh_len + HandshakeHash::STATE_LEN
+}
+
+const fn half_state_len(dh_len: usize) -> usize {
+    header_len(dh_len) + cipher_state::STATE_LEN
+}
+
+const fn full_state_len(dh_len: usize) -> usize {
+    header_len(dh_len) + 2 * cipher_state::STATE_LEN
+}
+
+/// A finished handshake. `remote_static` is all zero for patterns in which
+/// the remote party never sends its static key.
+pub struct Transport<C: Cipher = ChaChaPoly, D: Dh = X25519> {
+    pub(crate) rs: D::Key,
+    pub(crate) h: HandshakeHash,
+    pub(crate) send: CipherState<C>,
+    pub(crate) recv: CipherState<C>,
+}
+
+pub struct NoiseRead<C: Cipher = ChaChaPoly, D: Dh = X25519> {
+    pub(crate) recv: CipherState<C>,
+    pub(crate) rs: D::Key,
+    pub(crate) h: HandshakeHash,
 }
 
-pub struct NoiseRead {
-    pub(crate) recv: CipherState,
-    pub(crate) rs: [u8; 32],
+pub struct NoiseWrite<C: Cipher = ChaChaPoly, D: Dh = X25519> {
+    pub(crate) send: CipherState<C>,
+    pub(crate) rs: D::Key,
+    pub(crate) h: HandshakeHash,
 }
 
-pub struct NoiseWrite {
-    pub(crate) send: CipherState,
-    pub(crate) rs: [u8; 32],
+impl<C: Cipher> Transport<C> {
+    pub fn remote_key(&self) -> PublicKey {
+        PublicKey::from(self.rs)
+    }
 }
 
-impl Transport {
-    pub fn remote_key(&self) -> [u8; 32] {
+impl<C: Cipher, D: Dh> Transport<C, D> {
+    pub const fn overhead() -> usize {
+        TAG_LEN
+    }
+    pub fn remote_static(&self) -> D::Key {
         self.rs
     }
+    pub fn handshake_hash(&self) -> &[u8] {
+        self.h.as_slice()
+    }
     pub fn set_receive_nonce(&mut self, nonce: u64) {
         self.recv.set_nonce(nonce)
     }
@@ -29,15 +72,23 @@ impl Transport {
     pub fn recv_nonce(&self) -> u64 {
         self.recv.n
     }
-    pub fn split(self) -> (NoiseRead, NoiseWrite) {
+    pub fn rekey_outgoing(&mut self) {
+        self.send.rekey()
+    }
+    pub fn rekey_incoming(&mut self) {
+        self.recv.rekey()
+    }
+    pub fn split(self) -> (NoiseRead<C, D>, NoiseWrite<C, D>) {
         (
             NoiseRead {
                 recv: self.recv,
                 rs: self.rs,
+                h: self.h,
             },
             NoiseWrite {
                 send: self.send,
                 rs: self.rs,
+                h: self.h,
             },
         )
     }
@@ -47,23 +98,391 @@ impl Transport {
     pub fn write_message(&mut self, payload: &[u8], message: &mut [u8]) -> Result<usize, Error> {
         self.send.encrypt_with_ad(&[], payload, message)
     }
+
+    pub const fn export_len() -> usize {
+        SEAL_NONCE_LEN + full_state_len(D::LEN) + TAG_LEN
+    }
+    /// Writes the session, encrypted under `key`, into `blob`. `nonce` must
+    /// never repeat for the same `key`.
+    pub fn export(&self, key: &[u8; 32], nonce: u64, blob: &mut [u8]) -> Result<usize, Error> {
+        let state_len = full_state_len(D::LEN);
+        seal::<C>(key, nonce, b"Transport", state_len, blob, |state| {
+            let state = export_header::<D>(&self.rs, &self.h, state);
+            let (send, recv) = state.split_at_mut(cipher_state::STATE_LEN);
+            self.send.export(send);
+            self.recv.export(recv);
+        })
+    }
+    pub fn import(key: &[u8; 32], blob: &[u8]) -> Result<Self, Error> {
+        let mut buf = [0u8; MAX_FULL_STATE_LEN];
+        let state = &mut buf[..full_state_len(D::LEN)];
+        let transport = open::<C>(key, b"Transport", blob, state).and_then(|_| {
+            let (rs, h, state) = import_header::<D>(state)?;
+            let (send, recv) = state.split_at(cipher_state::STATE_LEN);
+            Ok(Self {
+                rs,
+                h,
+                send: CipherState::import(send),
+                recv: CipherState::import(recv),
+            })
+        });
+        buf.zeroize();
+        transport
+    }
 }
 
-impl NoiseRead {
-    pub fn remote_key(&self) -> [u8; 32] {
+impl<C: Cipher> NoiseRead<C> {
+    pub fn remote_key(&self) -> PublicKey {
+        PublicKey::from(self.rs)
+    }
+}
+
+impl<C: Cipher, D: Dh> NoiseRead<C, D> {
+    pub const fn overhead() -> usize {
+        TAG_LEN
+    }
+    pub fn remote_static(&self) -> D::Key {
         self.rs
     }
+    pub fn handshake_hash(&self) -> &[u8] {
+        self.h.as_slice()
+    }
+    pub fn rekey(&mut self) {
+        self.recv.rekey()
+    }
 
     pub fn read_message(&mut self, message: &[u8], payload: &mut [u8]) -> Result<usize, Error> {
         self.recv.decrypt_with_ad(&[], message, payload)
     }
+
+    pub const fn export_len() -> usize {
+        SEAL_NONCE_LEN + half_state_len(D::LEN) + TAG_LEN
+    }
+    pub fn export(&self, key: &[u8; 32], nonce: u64, blob: &mut [u8]) -> Result<usize, Error> {
+        let state_len = half_state_len(D::LEN);
+        seal::<C>(key, nonce, b"NoiseRead", state_len, blob, |state| {
+            self.recv
+                .export(export_header::<D>(&self.rs, &self.h, state))
+        })
+    }
+    pub fn import(key: &[u8; 32], blob: &[u8]) -> Result<Self, Error> {
+        let mut buf = [0u8; MAX_FULL_STATE_LEN];
+        let state = &mut buf[..half_state_len(D::LEN)];
+        let read = open::<C>(key, b"NoiseRead", blob, state).and_then(|_| {
+            let (rs, h, state) = import_header::<D>(state)?;
+            Ok(Self {
+                recv: CipherState::import(state),
+                rs,
+                h,
+            })
+        });
+        buf.zeroize();
+        read
+    }
+}
+
+impl<C: Cipher> NoiseWrite<C> {
+    pub fn remote_key(&self) -> PublicKey {
+        PublicKey::from(self.rs)
+    }
 }
 
-impl NoiseWrite {
-    pub fn remote_key(&self) -> [u8; 32] {
+impl<C: Cipher, D: Dh> NoiseWrite<C, D> {
+    pub const fn overhead() -> usize {
+        TAG_LEN
+    }
+    pub fn remote_static(&self) -> D::Key {
         self.rs
     }
+    pub fn handshake_hash(&self) -> &[u8] {
+        self.h.as_slice()
+    }
+    pub fn rekey(&mut self) {
+        self.send.rekey()
+    }
     pub fn write_message(&mut self, payload: &[u8], message: &mut [u8]) -> Result<usize, Error> {
         self.send.encrypt_with_ad(&[], payload, message)
     }
+
+    pub const fn export_len() -> usize {
+        SEAL_NONCE_LEN + half_state_len(D::LEN) + TAG_LEN
+    }
+    pub fn export(&self, key: &[u8; 32], nonce: u64, blob: &mut [u8]) -> Result<usize, Error> {
+        let state_len = half_state_len(D::LEN);
+        seal::<C>(key, nonce, b"NoiseWrite", state_len, blob, |state| {
+            self.send
+                .export(export_header::<D>(&self.rs, &self.h, state))
+        })
+    }
+    pub fn import(key: &[u8; 32], blob: &[u8]) -> Result<Self, Error> {
+        let mut buf = [0u8; MAX_FULL_STATE_LEN];
+        let state = &mut buf[..half_state_len(D::LEN)];
+        let write = open::<C>(key, b"NoiseWrite", blob, state).and_then(|_| {
+            let (rs, h, state) = import_header::<D>(state)?;
+            Ok(Self {
+                send: CipherState::import(state),
+                rs,
+                h,
+            })
+        });
+        buf.zeroize();
+        write
+    }
+}
+
+// Exported blobs are `nonce || AEAD(key, nonce, label, state)`; the label
+// keeps one kind of blob from being imported as another.
+fn seal<C: Cipher>(
+    key: &[u8; 32],
+    nonce: u64,
+    label: &[u8],
+    state_len: usize,
+    blob: &mut [u8],
+    export: impl FnOnce(&mut [u8]),
+) -> Result<usize, Error> {
+    let len = SEAL_NONCE_LEN + state_len + TAG_LEN;
+    if blob.len() < len {
+        return Err(Error::Input);
+    }
+    let (blob_nonce, rest) = blob.split_at_mut(SEAL_NONCE_LEN);
+    let (state, rest) = rest.split_at_mut(state_len);
+
+    blob_nonce.copy_from_slice(&nonce.to_be_bytes());
+    export(state);
+    let tag = C::encrypt(key, nonce, label, state);
+    rest[..TAG_LEN].copy_from_slice(&tag);
+    Ok(len)
+}
+
+fn open<C: Cipher>(
+    key: &[u8; 32],
+    label: &[u8],
+    blob: &[u8],
+    state: &mut [u8],
+) -> Result<(), Error> {
+    if blob.len() != SEAL_NONCE_LEN + state.len() + TAG_LEN {
+        return Err(Error::Input);
+    }
+    let (nonce, rest) = blob.split_at(SEAL_NONCE_LEN);
+    let (sealed, tag) = rest.split_at(state.len());
+
+    state.copy_from_slice(sealed);
+    C::decrypt(
+        key,
+        u64::from_be_bytes(nonce.try_into().unwrap()),
+        label,
+        state,
+        tag,
+    )
+}
+
+fn export_header<'a, D: Dh>(rs: &D::Key, h: &HandshakeHash, state: &'a mut [u8]) -> &'a mut [u8] {
+    let (header, rest) = state.split_at_mut(header_len(D::LEN));
+    header[..D::LEN].copy_from_slice(rs.as_ref());
+    h.export(&mut header[D::LEN..]);
+    rest
+}
+
+fn import_header<D: Dh>(state: &[u8]) -> Result<(D::Key, HandshakeHash, &[u8]), Error> {
+    let (header, rest) = state.split_at(header_len(D::LEN));
+    let rs = key::<D>(&header[..D::LEN])?;
+    let h = HandshakeHash::import(&header[D::LEN..])?;
+    Ok((rs, h, rest))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn transports() -> (Transport, Transport) {
+        let mut msg = [0u8; 200];
+        let mut payload = [0u8; 200];
+
+        let init = initiator(
+            EphemeralKeypair::from_secret([0u8; 32]),
+            &StaticKeypair::from_secret([1u8; 32]),
+            &[],
+        );
+        let resp = responder(
+            EphemeralKeypair::from_secret([2u8; 32]),
+            &StaticKeypair::from_secret([3u8; 32]),
+            &[],
+        );
+        let (len, init) = init.write_message(&[], &mut msg).unwrap();
+        let (_, resp) = resp.read_message(&msg[..len], &mut payload).unwrap();
+        let (len, r_trans) = resp.write_message(&[], &mut msg).unwrap();
+        let (_, i_trans) = init.read_message(&msg[..len], &mut payload).unwrap();
+        (i_trans, r_trans)
+    }
+
+    #[test]
+    fn test_rekey_snow() {
+        let e = [0u8; 32];
+        let s = [1u8; 32];
+        let re = [2u8; 32];
+        let rs = [3u8; 32];
+
+        let mut snow_buf = [0u8; 200];
+        let mut my_buf = [0u8; 200];
+
+        let prot_name = "Noise_IX_25519_ChaChaPoly_BLAKE2s".parse().unwrap();
+        let mut snow_init = snow::Builder::new(prot_name)
+            .local_private_key(&s)
+            .fixed_ephemeral_key_for_testing_only(&e)
+            .build_initiator()
+            .unwrap();
+        let resp = responder(
+            EphemeralKeypair::from_secret(re),
+            &StaticKeypair::from_secret(rs),
+            &[],
+        );
+
+        let len = snow_init.write_message(&[], &mut snow_buf).unwrap();
+        let (_, resp) = resp.read_message(&snow_buf[..len], &mut my_buf).unwrap();
+        let (len, mut r_trans) = resp.write_message(&[], &mut my_buf).unwrap();
+        snow_init
+            .read_message(&my_buf[..len], &mut snow_buf)
+            .unwrap();
+        let mut snow_i_trans = snow_init.into_transport_mode().unwrap();
+
+        r_trans.rekey_outgoing();
+        snow_i_trans.rekey_incoming();
+        let len = r_trans.write_message(b"rekeyed", &mut my_buf).unwrap();
+        let len = snow_i_trans
+            .read_message(&my_buf[..len], &mut snow_buf)
+            .unwrap();
+        assert_eq!(&snow_buf[..len], b"rekeyed");
+
+        snow_i_trans.rekey_outgoing();
+        r_trans.rekey_incoming();
+        let len = snow_i_trans
+            .write_message(b"rekeyed", &mut snow_buf)
+            .unwrap();
+        let len = r_trans.read_message(&snow_buf[..len], &mut my_buf).unwrap();
+        assert_eq!(&my_buf[..len], b"rekeyed");
+    }
+
+    #[test]
+    fn test_rekey_split() {
+        let mut msg = [0u8; 100];
+        let mut payload = [0u8; 100];
+        let (i_trans, r_trans) = transports();
+        let (_, mut i_write) = i_trans.split();
+        let (mut r_read, _) = r_trans.split();
+
+        i_write.rekey();
+        let len = i_write.write_message(b"hello", &mut msg).unwrap();
+        assert!(matches!(
+            r_read.read_message(&msg[..len], &mut payload),
+            Err(Error::Decrypt)
+        ));
+
+        r_read.rekey();
+        let len = r_read.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"hello");
+    }
+
+    #[test]
+    fn test_export_import() {
+        let key = [7u8; 32];
+        let mut blob = [0u8; 256];
+        let mut msg = [0u8; 100];
+        let mut payload = [0u8; 100];
+        let (mut i_trans, mut r_trans) = transports();
+
+        let len = i_trans.write_message(b"before", &mut msg).unwrap();
+        r_trans.read_message(&msg[..len], &mut payload).unwrap();
+
+        let blob_len = i_trans.export(&key, 0, &mut blob).unwrap();
+        assert_eq!(blob_len, Transport::<ChaChaPoly>::export_len());
+        let remote_key = i_trans.remote_key();
+        drop(i_trans);
+
+        let mut i_trans: Transport = Transport::import(&key, &blob[..blob_len]).unwrap();
+        assert_eq!(i_trans.remote_key(), remote_key);
+        assert_eq!(i_trans.handshake_hash(), r_trans.handshake_hash());
+
+        let len = i_trans.write_message(b"after", &mut msg).unwrap();
+        let len = r_trans.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"after");
+        let len = r_trans.write_message(b"back", &mut msg).unwrap();
+        let len = i_trans.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"back");
+
+        assert!(matches!(
+            Transport::<ChaChaPoly>::import(&[8u8; 32], &blob[..blob_len]),
+            Err(Error::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn test_export_import_split() {
+        let key = [7u8; 32];
+        let mut read_blob = [0u8; 256];
+        let mut write_blob = [0u8; 256];
+        let mut msg = [0u8; 100];
+        let mut payload = [0u8; 100];
+        let (i_trans, r_trans) = transports();
+        let (i_read, i_write) = i_trans.split();
+        let (mut r_read, mut r_write) = r_trans.split();
+
+        let read_len = i_read.export(&key, 0, &mut read_blob).unwrap();
+        let write_len = i_write.export(&key, 1, &mut write_blob).unwrap();
+        assert!(matches!(
+            NoiseWrite::<ChaChaPoly>::import(&key, &read_blob[..read_len]),
+            Err(Error::Decrypt)
+        ));
+        let mut i_read: NoiseRead = NoiseRead::import(&key, &read_blob[..read_len]).unwrap();
+        let mut i_write: NoiseWrite = NoiseWrite::import(&key, &write_blob[..write_len]).unwrap();
+
+        let len = i_write.write_message(b"hello", &mut msg).unwrap();
+        let len = r_read.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"hello");
+        let len = r_write.write_message(b"hi", &mut msg).unwrap();
+        let len = i_read.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"hi");
+    }
+
+    #[test]
+    fn test_nonce_exhausted() {
+        let mut msg = [0u8; 100];
+        let mut payload = [0u8; 100];
+        let (mut i_trans, mut r_trans) = transports();
+
+        i_trans.send.set_nonce(u64::MAX - 1);
+        r_trans.set_receive_nonce(u64::MAX - 1);
+        let len = i_trans.write_message(b"last", &mut msg).unwrap();
+        let len = r_trans.read_message(&msg[..len], &mut payload).unwrap();
+        assert_eq!(&payload[..len], b"last");
+
+        assert!(matches!(
+            i_trans.write_message(b"one more", &mut msg),
+            Err(Error::Nonce)
+        ));
+        assert!(matches!(
+            r_trans.read_message(&msg, &mut payload),
+            Err(Error::Nonce)
+        ));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_transport_read(
+            message in proptest::collection::vec(proptest::num::u8::ANY, 0..256),
+            payload_len in 0..256usize,
+        ) {
+            let mut payload = [0u8; 256];
+            let (mut i_trans, _) = transports();
+            let _ = i_trans.read_message(&message, &mut payload[..payload_len]);
+            let mut datagram = i_trans.into_datagram();
+            let _ = datagram.read_message(&message, &mut payload[..payload_len]);
+        }
+
+        #[test]
+        fn prop_import(blob in proptest::collection::vec(proptest::num::u8::ANY, 0..256)) {
+            let _ = Transport::<ChaChaPoly>::import(&[7u8; 32], &blob);
+            let _ = NoiseRead::<ChaChaPoly>::import(&[7u8; 32], &blob);
+            let _ = NoiseWrite::<ChaChaPoly>::import(&[7u8; 32], &blob);
+        }
+    }
 }
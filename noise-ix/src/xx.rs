@@ -0,0 +1,271 @@
+//! Noise XX pattern.
+//!
+//! ```text
+//! -> e
+//! <- e, ee, s, es
+//! -> s, se
+//! ```
+//!
+//! Unlike IX, the initiator's static key is only sent in the third message,
+//! encrypted, so a passive observer cannot tell which device is connecting.
+
+use crate::{
+    pattern, Blake2s, ChaChaPoly, Cipher, EphemeralKeypair, Error, HandshakeKeys, HandshakeState,
+    Hash, PublicKey, StaticKeypair, Transport,
+};
+
+pub fn initiator(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Initiator1 {
+    Initiator1::new(e, s, prologue)
+}
+
+pub fn responder(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Responder1 {
+    Responder1::new(e, s, prologue)
+}
+
+pub struct Initiator1<C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+}
+
+pub struct Initiator2<C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+}
+
+pub struct Initiator3<C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+}
+
+pub struct Responder1<C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+}
+
+pub struct Responder2<C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+}
+
+pub struct Responder3<C: Cipher = ChaChaPoly, H: Hash = Blake2s> {
+    state: HandshakeState<C, H>,
+}
+
+impl<C: Cipher, H: Hash> Initiator1<C, H> {
+    pub fn new(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Self {
+        let keys = HandshakeKeys {
+            s: Some(s.to_secret()),
+            e: Some(e.into_secret()),
+            ..HandshakeKeys::default()
+        };
+        Self {
+            state: HandshakeState::new(&pattern::XX, true, prologue, keys).unwrap(),
+        }
+    }
+
+    pub const fn overhead() -> usize {
+        pattern::XX.overhead(0)
+    }
+
+    pub fn write_message(
+        mut self,
+        payload: &[u8],
+        message: &mut [u8],
+    ) -> Result<(usize, Initiator2<C, H>), Error> {
+        let len = self.state.write_message(payload, message)?;
+        Ok((len, Initiator2 { state: self.state }))
+    }
+}
+
+impl<C: Cipher, H: Hash> Responder1<C, H> {
+    pub fn new(e: EphemeralKeypair, s: &StaticKeypair, prologue: &[u8]) -> Self {
+        let keys = HandshakeKeys {
+            s: Some(s.to_secret()),
+            e: Some(e.into_secret()),
+            ..HandshakeKeys::default()
+        };
+        Self {
+            state: HandshakeState::new(&pattern::XX, false, prologue, keys).unwrap(),
+        }
+    }
+
+    pub const fn overhead() -> usize {
+        pattern::XX.overhead(0)
+    }
+
+    pub fn read_message(
+        mut self,
+        message: &[u8],
+        payload: &mut [u8],
+    ) -> Result<(usize, Responder2<C, H>), Error> {
+        let len = self.state.read_message(message, payload)?;
+        Ok((len, Responder2 { state: self.state }))
+    }
+}
+
+impl<C: Cipher, H: Hash> Responder2<C, H> {
+    pub const fn overhead() -> usize {
+        pattern::XX.overhead(1)
+    }
+
+    pub fn write_message(
+        mut self,
+        payload: &[u8],
+        message: &mut [u8],
+    ) -> Result<(usize, Responder3<C, H>), Error> {
+        let len = self.state.write_message(payload, message)?;
+        Ok((len, Responder3 { state: self.state }))
+    }
+}
+
+impl<C: Cipher, H: Hash> Initiator2<C, H> {
+    pub const fn overhead() -> usize {
+        pattern::XX.overhead(1)
+    }
+
+    pub fn read_message(
+        mut self,
+        message: &[u8],
+        payload: &mut [u8],
+    ) -> Result<(usize, Initiator3<C, H>), Error> {
+        let len = self.state.read_message(message, payload)?;
+        Ok((len, Initiator3 { state: self.state }))
+    }
+}
+
+impl<C: Cipher, H: Hash> Initiator3<C, H> {
+    pub const fn overhead() -> usize {
+        pattern::XX.overhead(2)
+    }
+    pub fn remote_key(&self) -> PublicKey {
+        PublicKey::from(self.state.remote_static().unwrap_or_default())
+    }
+
+    pub fn write_message(
+        mut self,
+        payload: &[u8],
+        message: &mut [u8],
+    ) -> Result<(usize, Transport<C>), Error> {
+        let len = self.state.write_message(payload, message)?;
+        Ok((len, self.state.into_transport()?))
+    }
+}
+
+impl<C: Cipher, H: Hash> Responder3<C, H> {
+    pub const fn overhead() -> usize {
+        pattern::XX.overhead(2)
+    }
+
+    pub fn read_message(
+        mut self,
+        message: &[u8],
+        payload: &mut [u8],
+    ) -> Result<(usize, Transport<C>), Error> {
+        let len = self.state.read_message(message, payload)?;
+        Ok((len, self.state.into_transport()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dh, X25519};
+    extern crate alloc;
+
+    #[test]
+    fn test_xx_snow() {
+        let e = [0u8; 32];
+        let s = [1u8; 32];
+        let re = [2u8; 32];
+        let rs = [3u8; 32];
+
+        let mut snow_buf_init = [0u8; 200];
+        let mut snow_buf_resp = [0u8; 200];
+        let mut my_buf_init = [0u8; 200];
+        let mut my_buf_resp = [0u8; 200];
+
+        let prot_name = alloc::format!("Noise_{}_25519_ChaChaPoly_BLAKE2s", pattern::XX.name);
+        let mut snow_init = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&s)
+            .fixed_ephemeral_key_for_testing_only(&e)
+            .build_initiator()
+            .unwrap();
+
+        let mut snow_resp = snow::Builder::new(prot_name.parse().unwrap())
+            .local_private_key(&rs)
+            .fixed_ephemeral_key_for_testing_only(&re)
+            .build_responder()
+            .unwrap();
+        let my_init = initiator(
+            EphemeralKeypair::from_secret(e),
+            &StaticKeypair::from_secret(s),
+            &[],
+        );
+        let my_resp = responder(
+            EphemeralKeypair::from_secret(re),
+            &StaticKeypair::from_secret(rs),
+            &[],
+        );
+
+        // -> e
+        let len = snow_init.write_message(b"one", &mut snow_buf_init).unwrap();
+        let (my_len, init) = my_init.write_message(b"one", &mut my_buf_init).unwrap();
+        assert_eq!(snow_buf_init[..len], my_buf_init[..my_len]);
+        assert_eq!(my_len, <Initiator1>::overhead() + 3);
+
+        snow_resp
+            .read_message(&my_buf_init[..len], &mut snow_buf_resp)
+            .unwrap();
+        let (plen, resp) = my_resp
+            .read_message(&snow_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..plen], b"one");
+
+        // <- e, ee, s, es
+        let len = snow_resp.write_message(b"two", &mut snow_buf_resp).unwrap();
+        let (my_len, resp) = resp.write_message(b"two", &mut my_buf_resp).unwrap();
+        assert_eq!(snow_buf_resp[..len], my_buf_resp[..my_len]);
+        assert_eq!(my_len, <Responder2>::overhead() + 3);
+
+        snow_init
+            .read_message(&my_buf_resp[..len], &mut snow_buf_init)
+            .unwrap();
+        let (plen, init) = init
+            .read_message(&snow_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..plen], b"two");
+        assert_eq!(init.remote_key(), PublicKey::from(X25519::pub_key(&rs)));
+
+        // -> s, se
+        let len = snow_init
+            .write_message(b"three", &mut snow_buf_init)
+            .unwrap();
+        let (my_len, mut i_trans) = init.write_message(b"three", &mut my_buf_init).unwrap();
+        assert_eq!(snow_buf_init[..len], my_buf_init[..my_len]);
+        assert_eq!(my_len, <Initiator3>::overhead() + 5);
+
+        snow_resp
+            .read_message(&my_buf_init[..len], &mut snow_buf_resp)
+            .unwrap();
+        let (plen, mut r_trans) = resp
+            .read_message(&snow_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..plen], b"three");
+        assert_eq!(r_trans.remote_key(), PublicKey::from(X25519::pub_key(&s)));
+
+        assert_eq!(i_trans.handshake_hash(), snow_init.get_handshake_hash());
+        assert_eq!(r_trans.handshake_hash(), snow_resp.get_handshake_hash());
+
+        let mut snow_i_trans = snow_init.into_transport_mode().unwrap();
+        let mut snow_r_trans = snow_resp.into_transport_mode().unwrap();
+
+        let len = i_trans.write_message(b"hell no", &mut my_buf_init).unwrap();
+        let len = snow_r_trans
+            .read_message(&my_buf_init[..len], &mut my_buf_resp)
+            .unwrap();
+        assert_eq!(&my_buf_resp[..len], b"hell no");
+
+        let len = r_trans
+            .write_message(b"hell yes", &mut my_buf_resp)
+            .unwrap();
+        let len = snow_i_trans
+            .read_message(&my_buf_resp[..len], &mut my_buf_init)
+            .unwrap();
+        assert_eq!(&my_buf_init[..len], b"hell yes");
+    }
+}
@@ -0,0 +1,6 @@
+ALTER TABLE public.entity ADD COLUMN "session_ticket" bytea NULL;
+
+-- storing a ticket is not a change of the entity's data
+DROP TRIGGER IF EXISTS insert_logging on entity;
+CREATE TRIGGER insert_logging AFTER INSERT OR UPDATE OF entity_data ON entity
+FOR EACH ROW EXECUTE PROCEDURE log_entity();
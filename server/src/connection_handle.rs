@@ -1,24 +1,40 @@
 use async_std::{prelude::StreamExt, sync::Mutex};
+use noise_ix::{EphemeralKeypair, PublicKey};
 use once_cell::sync::Lazy;
 use serde_json::Value;
-use std::{collections::HashMap, io, sync::Arc};
-use tide::{Request, Result};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tide::Result;
 use tide_websockets::{Message, WebSocketConnection as Connection};
-use utils::{decode_cbor, encode_cbor};
+use utils::{
+    diag,
+    protocol::{
+        fingerprint, HANDSHAKE_IK, HANDSHAKE_IK_HFS, HANDSHAKE_IX, HANDSHAKE_IX_HFS,
+        HANDSHAKE_RESUME, REKEY_AFTER_MESSAGES, REKEY_AFTER_MINUTES,
+    },
+    Cbor,
+};
 
 use crate::database;
 
 type Pool = HashMap<Vec<u8>, Arc<Mutex<dyn ObjSender>>>;
 static POOL: Lazy<Mutex<Pool>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
-async fn insert_sender(key: &[u8], sender: impl ObjSender + 'static) -> Arc<Mutex<dyn ObjSender>> {
+async fn insert_sender<S: ObjSender + 'static>(key: &[u8], sender: S) -> Arc<Mutex<S>> {
     let sender = Arc::new(Mutex::new(sender));
     let p = POOL.lock();
     p.await.insert(key.to_vec(), sender.clone());
     sender
 }
 
-pub async fn run(_req: Request<()>, stream: Connection) -> Result<()> {
+/// Serves one connection, whose first message has a kind byte if `framed`
+/// and is a bare IX message otherwise.
+pub async fn run(stream: Connection, framed: bool) -> Result<()> {
     let mut read_stream = stream.clone().filter_map(|message| match message {
         Ok(Message::Binary(b)) => Some(b),
         _ => None,
@@ -29,59 +45,341 @@ pub async fn run(_req: Request<()>, stream: Connection) -> Result<()> {
         .await
         .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, ""))?;
 
-    let mut payload = vec![0u8; 1024];
-    let e = rand::random::<[u8; 32]>();
-    let (_, responder) = noise_ix::responder(e, *crate::vars::PKEY, &[])
-        .read_message(&b, &mut payload)
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+    let (kind, b) = split_kind(&b, framed)?;
 
-    let remote_key = responder.remote_key();
-    database::entity::create_entity(&remote_key).await?;
+    let e = EphemeralKeypair::generate(&mut rand::rngs::OsRng);
+    let (remote_key, msg, mut noise_read, noise_write, pending) = match kind {
+        HANDSHAKE_IX => {
+            let (_, responder) = noise_ix::responder(e, &crate::vars::PKEY, &[])
+                .read_message_vec(b)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+            let remote_key = responder.remote_key();
+            let (msg, transport) = responder
+                .write_message_vec(&[])
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+            log_session(&remote_key, transport.handshake_hash());
+            let (noise_read, noise_write) = transport.split();
+            (remote_key, msg, noise_read, noise_write, None)
+        }
+        HANDSHAKE_IK => {
+            let (_, responder) = noise_ix::ik::responder(e, &crate::vars::PKEY, &[])
+                .read_message_vec(b)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+            let remote_key = responder.remote_key();
+            let (msg, transport) = responder
+                .write_message_vec(&[])
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+            log_session(&remote_key, transport.handshake_hash());
+            let (noise_read, noise_write) = transport.split();
+            (remote_key, msg, noise_read, noise_write, None)
+        }
+        HANDSHAKE_IX_HFS => {
+            let (_, responder) = noise_ix::hybrid_responder(e, &crate::vars::PKEY, &[])
+                .read_message_vec(b)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+            let remote_key = responder.remote_key();
+            let (msg, transport) = responder
+                .write_message_vec(&[])
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+            log_session(&remote_key, transport.handshake_hash());
+            let (noise_read, noise_write) = transport.split();
+            (remote_key, msg, noise_read, noise_write, None)
+        }
+        HANDSHAKE_IK_HFS => {
+            let (_, responder) = noise_ix::ik::hybrid_responder(e, &crate::vars::PKEY, &[])
+                .read_message_vec(b)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+            let remote_key = responder.remote_key();
+            let (msg, transport) = responder
+                .write_message_vec(&[])
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+            log_session(&remote_key, transport.handshake_hash());
+            let (noise_read, noise_write) = transport.split();
+            (remote_key, msg, noise_read, noise_write, None)
+        }
+        HANDSHAKE_RESUME => {
+            if b.len() < 32 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "").into());
+            }
+            let (remote_key, message) = b.split_at(32);
+            let remote_key = PublicKey::try_from(remote_key)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+            let resumed = resume_session(remote_key, message).await;
+            // the ticket is spent by any attempt, so a device whose session
+            // went out of sync falls back to a full handshake next time
+            database::entity::set_session_ticket(remote_key.as_ref(), None).await?;
+            let (noise_read, noise_write, payload) = resumed.map_err(|e| {
+                tide::log::warn!("resume failed for {}: {}", remote_key.base64(), e);
+                e
+            })?;
+            log_session(&remote_key, noise_read.handshake_hash());
+            (remote_key, Vec::new(), noise_read, noise_write, payload)
+        }
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown handshake").into()),
+    };
 
-    let mut msg = [0u8; 96];
-    let (len, transport) = responder
-        .write_message(&[], &mut msg)
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
-    stream.send_bytes(msg[..len].to_vec()).await?;
+    database::entity::create_entity(remote_key.as_ref()).await?;
+    if !msg.is_empty() {
+        stream.send_bytes(msg).await?;
+    }
 
-    let (mut noise_read, noise_write) = transport.split();
-    let sender = insert_sender(&remote_key, (stream.clone(), noise_write)).await;
+    let sender = insert_sender(
+        remote_key.as_ref(),
+        NoiseSender::new(stream.clone(), noise_write),
+    )
+    .await;
 
-    while let Some(bytes) = read_stream.next().await {
-        let mut payload = vec![0u8; bytes.len() - 16];
+    let served: Result<()> = async {
+        if let Some(payload) = pending {
+            handle_payload(&remote_key, &sender, payload).await?;
+        }
+        while let Some(bytes) = read_stream.next().await {
+            if let Some(payload) = read_payload(&mut noise_read, &bytes)? {
+                handle_payload(&remote_key, &sender, payload).await?;
+            }
+        }
+        Ok(())
+    }
+    .await;
 
-        noise_read
-            .read_message(&bytes, &mut payload)
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+    // the ticket holds the nonces of the last message each side processed,
+    // so it is stored however the connection ended
+    let ticket = seal_session(&noise_read, &sender.lock().await.noise)?;
+    database::entity::set_session_ticket(remote_key.as_ref(), Some(&ticket)).await?;
+    served
+}
 
-        let payload: Value =
-            decode_cbor(&payload).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+fn split_kind(first: &[u8], framed: bool) -> io::Result<(u8, &[u8])> {
+    if !framed {
+        return Ok((HANDSHAKE_IX, first));
+    }
+    let (kind, b) = first
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+    Ok((*kind, b))
+}
 
-        if let Some(map) = payload.as_object() {
-            database::entity::upsert_data(&remote_key, map.clone()).await?;
-        }
-        //echo back
-        sender.lock().await.send(payload).await?;
+fn log_session(remote_key: &PublicKey, handshake_hash: &[u8]) {
+    tide::log::info!(
+        "session {} with {}",
+        fingerprint(handshake_hash),
+        remote_key.base64()
+    );
+}
+
+fn log_payload(direction: &str, payload: &[u8]) {
+    tide::log::debug!(
+        "{} {}",
+        direction,
+        diag::to_diag(payload).unwrap_or_else(|e| e.to_string())
+    );
+}
+
+/// Decrypts one transport message, returning `None` for the rekey marker.
+fn read_payload(noise_read: &mut noise_ix::NoiseRead, bytes: &[u8]) -> Result<Option<Cbor>> {
+    let payload = noise_read
+        .read_message_vec(bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+
+    if payload.is_empty() {
+        noise_read.rekey();
+        return Ok(None);
     }
+
+    log_payload("received", &payload);
+    let payload =
+        Cbor::from_slice(&payload).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+    Ok(Some(payload))
+}
+
+/// Encrypts `payload` as one transport message, the inverse of `read_payload`.
+fn write_payload(noise_write: &mut noise_ix::NoiseWrite, payload: &Cbor) -> io::Result<Vec<u8>> {
+    let payload = payload
+        .to_vec()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, ""))?;
+    log_payload("sent", &payload);
+    noise_write
+        .write_message_vec(&payload)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))
+}
+
+async fn handle_payload(
+    remote_key: &PublicKey,
+    sender: &Mutex<NoiseSender>,
+    payload: Cbor,
+) -> Result<()> {
+    if let Value::Object(map) = Value::from(payload.clone()) {
+        database::entity::upsert_data(remote_key.as_ref(), map).await?;
+    }
+    //echo back
+    sender.lock().await.send(payload).await?;
     Ok(())
 }
 
+// A ticket is the hub's read half followed by its write half, each sealed
+// with the ticket key.
+fn seal_session(
+    noise_read: &noise_ix::NoiseRead,
+    noise_write: &noise_ix::NoiseWrite,
+) -> io::Result<Vec<u8>> {
+    let read_len = noise_ix::NoiseRead::<noise_ix::ChaChaPoly>::export_len();
+    let write_len = noise_ix::NoiseWrite::<noise_ix::ChaChaPoly>::export_len();
+    let mut ticket = vec![0u8; read_len + write_len];
+    let (read_blob, write_blob) = ticket.split_at_mut(read_len);
+
+    let key = &*crate::vars::TICKET_KEY;
+    noise_read
+        .export(key, rand::random(), read_blob)
+        .and_then(|_| noise_write.export(key, rand::random(), write_blob))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+    Ok(ticket)
+}
+
+/// Restores the hub's half of a session from its ticket and reads the
+/// device's first message with it.
+async fn resume_session(
+    remote_key: PublicKey,
+    message: &[u8],
+) -> Result<(noise_ix::NoiseRead, noise_ix::NoiseWrite, Option<Cbor>)> {
+    let ticket = database::entity::get_session_ticket(remote_key.as_ref())
+        .await?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no session to resume"))?;
+    let read_len = noise_ix::NoiseRead::<noise_ix::ChaChaPoly>::export_len();
+    if ticket.len() < read_len {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "").into());
+    }
+    let (read_blob, write_blob) = ticket.split_at(read_len);
+
+    let key = &*crate::vars::TICKET_KEY;
+    let mut noise_read = noise_ix::NoiseRead::import(key, read_blob)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+    let noise_write = noise_ix::NoiseWrite::import(key, write_blob)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+    if noise_read.remote_key() != remote_key {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "").into());
+    }
+    let payload = read_payload(&mut noise_read, message)?;
+    Ok((noise_read, noise_write, payload))
+}
+
 #[async_trait::async_trait]
 pub(crate) trait ObjSender: Send + Sync {
-    async fn send(&mut self, obj: Value) -> Result<()>;
+    async fn send(&mut self, obj: Cbor) -> Result<()>;
+}
+
+pub(crate) struct NoiseSender {
+    stream: Connection,
+    noise: noise_ix::NoiseWrite,
+    sent: u64,
+    rekeyed_at: Instant,
+}
+
+impl NoiseSender {
+    fn new(stream: Connection, noise: noise_ix::NoiseWrite) -> Self {
+        Self {
+            stream,
+            noise,
+            sent: 0,
+            rekeyed_at: Instant::now(),
+        }
+    }
+
+    async fn rekey_if_due(&mut self) -> Result<()> {
+        let interval = Duration::from_secs(REKEY_AFTER_MINUTES * 60);
+        if self.sent < REKEY_AFTER_MESSAGES && self.rekeyed_at.elapsed() < interval {
+            return Ok(());
+        }
+
+        let message = self
+            .noise
+            .write_message_vec(&[])
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+        self.stream.send_bytes(message).await?;
+
+        self.noise.rekey();
+        self.sent = 0;
+        self.rekeyed_at = Instant::now();
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
-impl ObjSender for (Connection, noise_ix::NoiseWrite) {
-    async fn send(&mut self, obj: Value) -> Result<()> {
-        let mut buf = [0u8; 1024];
-        let written = encode_cbor(&obj, &mut buf).unwrap();
+impl ObjSender for NoiseSender {
+    async fn send(&mut self, obj: Cbor) -> Result<()> {
+        self.rekey_if_due().await?;
 
-        let mut message = Vec::new();
-        message.resize(written + 16, 0u8);
-        self.1.write_message(&buf[..written], &mut message).unwrap();
+        let message = write_payload(&mut self.noise, &obj)?;
+        self.sent += 1;
 
-        self.0.send_bytes(message).await?;
+        self.stream.send_bytes(message).await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use noise_ix::StaticKeypair;
+
+    #[test]
+    fn test_split_kind() {
+        // a resume frame: kind, public key and a 15-byte payload with its tag
+        let mut resume = vec![HANDSHAKE_RESUME];
+        resume.resize(64, 7);
+        let (kind, rest) = split_kind(&resume, true).unwrap();
+        assert_eq!((kind, rest), (HANDSHAKE_RESUME, &resume[1..]));
+        assert!(split_kind(&[], true).is_err());
+
+        // a bare IX message is read as one whatever its payload
+        let mut rng = rand::rngs::OsRng;
+        let s = StaticKeypair::generate(&mut rng);
+        let (message, _) = noise_ix::initiator(EphemeralKeypair::generate(&mut rng), &s, &[])
+            .write_message_vec(b"payload")
+            .unwrap();
+        let (kind, rest) = split_kind(&message, false).unwrap();
+        assert_eq!((kind, rest), (HANDSHAKE_IX, &message[..]));
+        let hub = StaticKeypair::generate(&mut rng);
+        let (payload, _) = noise_ix::responder(EphemeralKeypair::generate(&mut rng), &hub, &[])
+            .read_message_vec(rest)
+            .unwrap();
+        assert_eq!(payload, b"payload");
+    }
+
+    #[test]
+    fn test_device_payload_echo() {
+        let mut rng = rand::rngs::OsRng;
+        let (device, hub) = (
+            StaticKeypair::generate(&mut rng),
+            StaticKeypair::generate(&mut rng),
+        );
+        let (message, device_init) =
+            noise_ix::initiator(EphemeralKeypair::generate(&mut rng), &device, &[])
+                .write_message_vec(&[])
+                .unwrap();
+        let (_, hub_resp) = noise_ix::responder(EphemeralKeypair::generate(&mut rng), &hub, &[])
+            .read_message_vec(&message)
+            .unwrap();
+        let (message, hub_trans) = hub_resp.write_message_vec(&[]).unwrap();
+        let (_, mut device_trans) = device_init.read_message_vec(&message).unwrap();
+        let (mut hub_read, mut hub_write) = hub_trans.split();
+
+        // device state and integer keyed readings are not `TextMessage`s
+        for (payload, data) in &[
+            (
+                &b"\xa2\x62on\xf5\x65level\x03"[..],
+                serde_json::json!({"on": true, "level": 3}),
+            ),
+            (
+                &b"\xa2\x01\xf9\x4d\x20\x02\x18\x32"[..],
+                serde_json::json!({"$1": 20.5, "$2": 50}),
+            ),
+        ] {
+            let message = device_trans.write_message_vec(payload).unwrap();
+            let received = read_payload(&mut hub_read, &message).unwrap().unwrap();
+            assert_eq!(&Value::from(received.clone()), data);
+
+            let echo = write_payload(&mut hub_write, &received).unwrap();
+            assert_eq!(&device_trans.read_message_vec(&echo).unwrap(), payload);
+        }
+    }
+}
@@ -55,3 +55,32 @@ pub async fn _get_data(entity: &[u8]) -> Result<Map<String, Value>> {
     .into_iter()
     .collect())
 }
+
+pub async fn get_session_ticket(entity: &[u8]) -> Result<Option<Vec<u8>>> {
+    Ok(query!(
+        r#"
+        -- GET SESSION TICKET
+        select session_ticket from entity
+        where public_key = $1
+        "#,
+        entity
+    )
+    .fetch_optional(&*DB)
+    .await?
+    .and_then(|row| row.session_ticket))
+}
+
+pub async fn set_session_ticket(entity: &[u8], ticket: Option<&[u8]>) -> Result<()> {
+    query!(
+        r#"
+        -- SET SESSION TICKET
+        update entity set session_ticket = $2
+        where public_key = $1
+        "#,
+        entity,
+        ticket
+    )
+    .execute(&*DB)
+    .await?;
+    Ok(())
+}
@@ -3,13 +3,19 @@ mod database;
 mod vars;
 
 use tide_websockets::WebSocket;
+use utils::protocol::{FRAMED_PATH, LEGACY_PATH};
 
 pub fn app() -> anyhow::Result<tide::Server<()>> {
     let mut app = tide::new();
 
     app.at("/").get(tide::Redirect::new("/index.html"));
     app.at("/").serve_dir("../browser/dist/")?;
-    app.at("/ws").get(WebSocket::new(connection_handle::run));
+    app.at(FRAMED_PATH).get(WebSocket::new(|_, stream| {
+        connection_handle::run(stream, true)
+    }));
+    app.at(LEGACY_PATH).get(WebSocket::new(|_, stream| {
+        connection_handle::run(stream, false)
+    }));
     Ok(app)
 }
 
@@ -17,6 +23,7 @@ pub fn app() -> anyhow::Result<tide::Server<()>> {
 async fn main() -> anyhow::Result<()> {
     tide::log::start();
     database::migrate().await?;
+    tide::log::info!("hub public key: {}", vars::PKEY.public().base64());
     app()?
         .listen(format!("0.0.0.0:{}", *vars::WEB_PORT))
         .await?;
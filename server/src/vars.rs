@@ -1,9 +1,10 @@
+use noise_ix::StaticKeypair;
 use once_cell::sync::Lazy;
 use sha2::Digest;
 use std::env::var;
 
 pub static WEB_PORT: Lazy<String> = Lazy::new(|| {
-    if let Some(s) = var("PORT").ok() {
+    if let Ok(s) = var("PORT") {
         s
     } else {
         String::from("8080")
@@ -15,12 +16,20 @@ pub static DB_URL: Lazy<String> = Lazy::new(|| {
     url.expect("set DATABASE_URL to your postgres uri")
 });
 
-pub static PKEY: Lazy<[u8; 32]> = Lazy::new(|| {
+pub static PKEY: Lazy<StaticKeypair> = Lazy::new(|| {
     var("PRIVATE")
         .map(|s| {
             let mut sha256 = sha2::Sha256::default();
             sha256.update(s);
-            sha256.finalize().into()
+            StaticKeypair::from_secret(sha256.finalize().into())
         })
-        .unwrap_or(rand::random::<[u8; 32]>())
+        .unwrap_or_else(|_| StaticKeypair::generate(&mut rand::rngs::OsRng))
+});
+
+// Tickets stay valid across restarts as long as PRIVATE does not change.
+pub static TICKET_KEY: Lazy<[u8; 32]> = Lazy::new(|| {
+    let mut sha256 = sha2::Sha256::default();
+    sha256.update(b"session ticket");
+    sha256.update(PKEY.secret());
+    sha256.finalize().into()
 });
@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = utils::decode_cbor(data);
+    // what the hub runs on every decrypted payload
+    if let Ok(value) = utils::Cbor::from_slice(data) {
+        let _ = value.to_vec();
+        let _ = value.to_deterministic_vec();
+    }
+    let _ = utils::diag::to_diag(data);
+});
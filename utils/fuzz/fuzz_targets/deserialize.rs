@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = utils::de::from_slice::<utils::protocol::TextMessage>(data);
+    let _ = utils::de::from_slice::<serde_json::Value>(data);
+});
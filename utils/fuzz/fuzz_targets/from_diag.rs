@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok(buf) = utils::diag::from_diag(text) {
+            let _ = utils::diag::to_diag(&buf);
+        }
+    }
+});
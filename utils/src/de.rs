@@ -0,0 +1,417 @@
+//! A serde `Deserializer` reading CBOR written by `ser`, or by a device.
+//!
+//! Text and byte strings are borrowed from the input when they are not
+//! chunked, and tags are skipped, leaving their content.
+
+use minicbor::{data::Type, decode::Decoder};
+use serde::de::{self, DeserializeSeed, Visitor};
+
+use crate::{
+    value::{skip_break, MAX_DEPTH},
+    Cbor, Error,
+};
+
+/// Fails on anything left after the first CBOR value.
+pub fn from_slice<'de, T: de::Deserialize<'de>>(buf: &'de [u8]) -> Result<T, Error> {
+    let mut de = Deserializer {
+        d: Decoder::new(buf),
+        depth: 0,
+    };
+    let value = T::deserialize(&mut de)?;
+    if de.d.position() != buf.len() {
+        return Err(Error::Message("trailing data".into()));
+    }
+    Ok(value)
+}
+
+pub struct Deserializer<'de> {
+    d: Decoder<'de>,
+    depth: usize,
+}
+
+impl<'de> Deserializer<'de> {
+    fn skip_tags(&mut self) -> Result<Type, Error> {
+        while self.d.datatype()? == Type::Tag {
+            self.d.tag()?;
+        }
+        Ok(self.d.datatype()?)
+    }
+
+    // Arrays and maps count towards `MAX_DEPTH` like in `Cbor::from_slice`.
+    fn nested<T>(
+        &mut self,
+        len: Option<u64>,
+        visit: impl FnOnce(&mut Access<'_, 'de>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::Message("nesting too deep".into()));
+        }
+        self.depth += 1;
+        let mut access = Access { de: self, len };
+        let value = visit(&mut access)?;
+        let ended = match access.len {
+            Some(len) => len == 0,
+            None => self.d.datatype()? == Type::Break,
+        };
+        if !ended {
+            return Err(Error::Message("trailing array or map items".into()));
+        }
+        if len.is_none() {
+            skip_break(&mut self.d);
+        }
+        self.depth -= 1;
+        Ok(value)
+    }
+}
+
+/// The items of an array or a map, `len` of them or up to a break.
+struct Access<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    len: Option<u64>,
+}
+
+impl<'de> Access<'_, 'de> {
+    fn has_next(&mut self) -> Result<bool, Error> {
+        Ok(match &mut self.len {
+            Some(0) => false,
+            Some(len) => {
+                *len -= 1;
+                true
+            }
+            None => self.de.d.datatype()? != Type::Break,
+        })
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if !self.has_next()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+    fn size_hint(&self) -> Option<usize> {
+        self.len.map(|len| len as usize)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if !self.has_next()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+    fn size_hint(&self) -> Option<usize> {
+        self.len.map(|len| len as usize)
+    }
+}
+
+/// The variant of an enum, a name or a one entry map from it.
+struct Enum<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Enum<'_, 'de> {
+    type Error = Error;
+    fn unit_variant(self) -> Result<(), Error> {
+        de::Deserialize::deserialize(self.de)
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.de)
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.de, visitor)
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self.de, visitor)
+    }
+}
+
+/// A unit variant, given by its name alone.
+struct UnitVariant<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for UnitVariant<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for UnitVariant<'_, 'de> {
+    type Error = Error;
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, _seed: T) -> Result<T::Value, Error> {
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"newtype variant",
+        ))
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"tuple variant",
+        ))
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"struct variant",
+        ))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.skip_tags()? {
+            Type::Bool => visitor.visit_bool(self.d.bool()?),
+            Type::U8 | Type::U16 | Type::U32 | Type::U64 => visitor.visit_u64(self.d.u64()?),
+            // `i64` reads every width, the narrower readers reject e.g. -256
+            Type::I8 | Type::I16 | Type::I32 | Type::I64 => visitor.visit_i64(self.d.i64()?),
+            Type::F16 => visitor.visit_f32(self.d.f16()?),
+            Type::F32 => visitor.visit_f32(self.d.f32()?),
+            Type::F64 => visitor.visit_f64(self.d.f64()?),
+            Type::Bytes => visitor.visit_borrowed_bytes(self.d.bytes()?),
+            Type::BytesIndef => {
+                let mut bytes = Vec::new();
+                for chunk in self.d.bytes_iter()? {
+                    bytes.extend_from_slice(chunk?);
+                }
+                visitor.visit_byte_buf(bytes)
+            }
+            Type::String => visitor.visit_borrowed_str(self.d.str()?),
+            Type::StringIndef => {
+                let mut buf = String::new();
+                for chunk in self.d.str_iter()? {
+                    buf.push_str(chunk?);
+                }
+                visitor.visit_string(buf)
+            }
+            Type::Null => {
+                self.d.null()?;
+                visitor.visit_unit()
+            }
+            Type::Undefined => {
+                self.d.undefined()?;
+                visitor.visit_unit()
+            }
+            Type::Array | Type::ArrayIndef => {
+                let len = self.d.array()?;
+                self.nested(len, |access| visitor.visit_seq(access))
+            }
+            Type::Map | Type::MapIndef => {
+                let len = self.d.map()?;
+                self.nested(len, |access| visitor.visit_map(access))
+            }
+            Type::Break => Err(Error::Message("unexpected break".into())),
+            t => Err(Error::Message(format!("unsupported cbor type {:?}", t))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.skip_tags()? {
+            Type::Null => {
+                self.d.null()?;
+                visitor.visit_none()
+            }
+            Type::Undefined => {
+                self.d.undefined()?;
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.skip_tags()? {
+            Type::String | Type::StringIndef => visitor.visit_enum(UnitVariant { de: self }),
+            Type::Map | Type::MapIndef => {
+                let len = self.d.map()?;
+                if len != Some(1) {
+                    return Err(Error::Message("expected a one entry map".into()));
+                }
+                self.nested(len, |access| {
+                    access.has_next()?;
+                    visitor.visit_enum(Enum { de: access.de })
+                })
+            }
+            _ => Err(Error::Message("expected an enum".into())),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::Message("nesting too deep".into()));
+        }
+        <Cbor as minicbor::Decode>::decode(&mut self.d)?;
+        visitor.visit_unit()
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{decode_cbor, ser::to_vec};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Kind {
+        Switch,
+        Dimmer(u8),
+        Sensor { unit: String, scale: f32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct State<'a> {
+        name: &'a str,
+        #[serde(with = "serde_bytes_like")]
+        key: Vec<u8>,
+        on: bool,
+        level: Option<i64>,
+        history: Vec<(u64, f64)>,
+        kinds: Vec<Kind>,
+        extra: BTreeMap<String, u32>,
+    }
+
+    // `Vec<u8>` is a sequence to serde, this writes it as a byte string
+    mod serde_bytes_like {
+        use serde::{de, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(bytes)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+            struct Bytes;
+            impl<'de> de::Visitor<'de> for Bytes {
+                type Value = Vec<u8>;
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("bytes")
+                }
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+                    Ok(v.to_vec())
+                }
+            }
+            d.deserialize_bytes(Bytes)
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let state = State {
+            name: "lamp",
+            key: vec![1, 2, 3],
+            on: true,
+            level: None,
+            history: vec![(1363896240, 0.5), (1363896300, -4.1)],
+            kinds: vec![
+                Kind::Switch,
+                Kind::Dimmer(200),
+                Kind::Sensor {
+                    unit: "W".into(),
+                    scale: 1.5,
+                },
+            ],
+            extra: vec![("a".to_owned(), 1)].into_iter().collect(),
+        };
+        let buf = to_vec(&state).unwrap();
+        assert_eq!(from_slice::<State>(&buf).unwrap(), state);
+
+        // the same CBOR as going through a JSON value, apart from the bytes
+        let json = decode_cbor(&buf).unwrap();
+        assert_eq!(json["key"], serde_json::json!({"$bytes": "AQID"}));
+        assert_eq!(json["level"], serde_json::Value::Null);
+        assert_eq!(
+            json["kinds"],
+            serde_json::json!(["Switch", {"Dimmer": 200}, {"Sensor": {"unit": "W", "scale": 1.5}}])
+        );
+        assert_eq!(&buf[..6], b"\xa7\x64name");
+    }
+
+    #[test]
+    fn test_device_input() {
+        // {_ "b": [_ 1, -256], "a": 1(1.5), "c": "x"} with an unknown field
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Reading {
+            a: f64,
+            b: Vec<i32>,
+        }
+        let buf = b"\xbf\x61b\x9f\x01\x38\xff\xff\x61a\xc1\xf9\x3e\x00\x61c\x61x\xff";
+        assert_eq!(
+            from_slice::<Reading>(buf).unwrap(),
+            Reading {
+                a: 1.5,
+                b: vec![1, -256]
+            }
+        );
+
+        assert!(from_slice::<Reading>(&[buf.as_ref(), b"\x00"].concat()).is_err());
+        assert!(from_slice::<(u8, u8)>(b"\x83\x01\x02\x03").is_err());
+        assert!(from_slice::<u8>(b"\x19\x01\x00").is_err());
+        assert!(from_slice::<Kind>(b"\xa2\x66Dimmer\x01\x66Switch\xf6").is_err());
+
+        let mut deep = vec![0x81u8; 100_000];
+        deep.push(0xf6);
+        assert!(from_slice::<serde_json::Value>(&deep).is_err());
+    }
+}
@@ -0,0 +1,734 @@
+//! CBOR diagnostic notation, RFC 8949 section 8.
+//!
+//! `to_diag` shows what a JSON conversion hides: `h'0102'` bytes next to
+//! `"text"`, `1` next to `1.0`, `[_ ...]` for indefinite lengths and `1(...)`
+//! for tags. An integer or float that is not in its shortest form gets an
+//! encoding indicator, e.g. `1_1` for `0x190001` and `1.5_2` for a single
+//! precision 1.5. A NaN other than the quiet NaN is written as its bits,
+//! e.g. `NaN'7e01'` for `0xf97e01`. `from_diag` reads the same notation back
+//! into CBOR, for writing test fixtures.
+
+use std::fmt::Write;
+
+use minicbor::{
+    data::Type,
+    decode::{self, Decoder},
+    encode::Encoder,
+};
+
+use crate::{
+    value::{encode_float, MAX_DEPTH},
+    Error, IoWrite,
+};
+
+pub fn to_diag(buf: &[u8]) -> Result<String, decode::Error> {
+    let mut printer = Printer {
+        buf,
+        d: Decoder::new(buf),
+        out: String::new(),
+    };
+    printer.item(0)?;
+    if printer.d.position() != buf.len() {
+        return Err(decode::Error::Message("trailing data"));
+    }
+    Ok(printer.out)
+}
+
+struct Printer<'b> {
+    buf: &'b [u8],
+    d: Decoder<'b>,
+    out: String,
+}
+
+impl Printer<'_> {
+    fn item(&mut self, depth: usize) -> Result<(), decode::Error> {
+        if depth > MAX_DEPTH {
+            return Err(decode::Error::Message("nesting too deep"));
+        }
+        let datatype = self.d.datatype()?;
+        let start = self.d.position();
+        let initial = self.buf[start];
+        match datatype {
+            Type::Bool => push(&mut self.out, self.d.bool()?),
+            Type::U8 | Type::U16 | Type::U32 | Type::U64 => {
+                let n = self.d.u64()?;
+                push(&mut self.out, n);
+                int_indicator(&mut self.out, initial, n);
+            }
+            Type::I8 | Type::I16 | Type::I32 | Type::I64 => {
+                let n = self.d.i64()?;
+                push(&mut self.out, n);
+                int_indicator(&mut self.out, initial, !(n as u64));
+            }
+            Type::F16 | Type::F32 | Type::F64 => {
+                let n = match datatype {
+                    Type::F16 => self.d.f16()?.into(),
+                    Type::F32 => self.d.f32()?.into(),
+                    _ => self.d.f64()?,
+                };
+                float(&mut self.out, &self.buf[start..self.d.position()], n);
+            }
+            Type::Bytes => bytes(&mut self.out, self.d.bytes()?),
+            Type::BytesIndef => {
+                self.out.push_str("(_ ");
+                for (i, chunk) in self.d.bytes_iter()?.enumerate() {
+                    separator(&mut self.out, i);
+                    bytes(&mut self.out, chunk?);
+                }
+                self.out.push(')');
+            }
+            Type::String => text(&mut self.out, self.d.str()?),
+            Type::StringIndef => {
+                self.out.push_str("(_ ");
+                for (i, chunk) in self.d.str_iter()?.enumerate() {
+                    separator(&mut self.out, i);
+                    text(&mut self.out, chunk?);
+                }
+                self.out.push(')');
+            }
+            Type::Array | Type::ArrayIndef => {
+                let len = self.d.array()?;
+                self.out.push('[');
+                self.items(len, depth, false)?;
+                self.out.push(']');
+            }
+            Type::Map | Type::MapIndef => {
+                let len = self.d.map()?;
+                self.out.push('{');
+                self.items(len, depth, true)?;
+                self.out.push('}');
+            }
+            Type::Tag => {
+                let tag = self.tag_number()?;
+                push(&mut self.out, tag);
+                self.out.push('(');
+                self.item(depth + 1)?;
+                self.out.push(')');
+            }
+            Type::Simple => {
+                let n = self.d.simple()?;
+                let _ = write!(self.out, "simple({})", n);
+            }
+            Type::Null => {
+                self.d.null()?;
+                self.out.push_str("null");
+            }
+            Type::Undefined => {
+                self.d.undefined()?;
+                self.out.push_str("undefined");
+            }
+            Type::Break => return Err(decode::Error::Message("unexpected break")),
+            t @ Type::Unknown(_) => {
+                return Err(decode::Error::TypeMismatch(t, "unknown cbor type"))
+            }
+        }
+        Ok(())
+    }
+
+    /// The items of an array, or the entries of a map, `len` of them or up
+    /// to a break.
+    fn items(&mut self, len: Option<u64>, depth: usize, map: bool) -> Result<(), decode::Error> {
+        if len.is_none() {
+            self.out.push_str("_ ");
+        }
+        let mut i = 0;
+        loop {
+            let more = match len {
+                Some(len) => i < len,
+                None => self.d.datatype()? != Type::Break,
+            };
+            if !more {
+                break;
+            }
+            separator(&mut self.out, i as usize);
+            self.item(depth + 1)?;
+            if map {
+                self.out.push_str(": ");
+                self.item(depth + 1)?;
+            }
+            i += 1;
+        }
+        if len.is_none() {
+            self.d.set_position(self.d.position() + 1);
+        }
+        Ok(())
+    }
+
+    fn tag_number(&mut self) -> Result<u64, decode::Error> {
+        // `Decoder::tag` maps the number to a `Tag`, the head has it as is
+        let start = self.d.position();
+        self.d.tag()?;
+        let head = &self.buf[start..self.d.position()];
+        Ok(match head.len() {
+            1 => u64::from(head[0] & 0x1f),
+            _ => head[1..].iter().fold(0, |n, b| n << 8 | u64::from(*b)),
+        })
+    }
+}
+
+fn push(out: &mut String, value: impl std::fmt::Display) {
+    let _ = write!(out, "{}", value);
+}
+
+fn separator(out: &mut String, i: usize) {
+    if i > 0 {
+        out.push_str(", ");
+    }
+}
+
+fn int_indicator(out: &mut String, initial: u8, n: u64) {
+    let info = initial & 0x1f;
+    if info >= 24 && info != shortest_info(n) {
+        let _ = write!(out, "_{}", info - 24);
+    }
+}
+
+/// `encoded` is the whole item, its initial byte and the float's bits.
+fn float(out: &mut String, encoded: &[u8], n: f64) {
+    let (initial, bits) = (encoded[0], &encoded[1..]);
+    let quiet_nan: &[u8] = match bits.len() {
+        2 => &[0x7e, 0],
+        4 => &[0x7f, 0xc0, 0, 0],
+        _ => &[0x7f, 0xf8, 0, 0, 0, 0, 0, 0],
+    };
+    if n.is_nan() && bits != quiet_nan {
+        // `NaN` would hide the sign and payload
+        out.push_str("NaN'");
+        for b in bits {
+            let _ = write!(out, "{:02x}", b);
+        }
+        out.push('\'');
+        return;
+    }
+    if n.is_nan() {
+        out.push_str("NaN");
+    } else if n.is_infinite() {
+        out.push_str(if n > 0.0 { "Infinity" } else { "-Infinity" });
+    } else {
+        // `Debug` always has a `.` or an exponent, and round trips
+        let _ = write!(out, "{:?}", n);
+    }
+    let mut shortest = Vec::new();
+    let _ = encode_float(n, &mut Encoder::new(IoWrite(&mut shortest)));
+    if shortest[0] != initial {
+        let _ = write!(out, "_{}", (initial & 0x1f) - 24);
+    }
+}
+
+fn bytes(out: &mut String, bytes: &[u8]) {
+    out.push_str("h'");
+    for b in bytes {
+        let _ = write!(out, "{:02x}", b);
+    }
+    out.push('\'');
+}
+
+fn text(out: &mut String, s: &str) {
+    // diagnostic notation strings are JSON strings
+    out.push_str(&serde_json::Value::from(s).to_string());
+}
+
+/// The additional information of the shortest head holding `n`.
+fn shortest_info(n: u64) -> u8 {
+    match n {
+        0..=23 => n as u8,
+        24..=0xff => 24,
+        0x100..=0xffff => 25,
+        0x1_0000..=0xffff_ffff => 26,
+        _ => 27,
+    }
+}
+
+/// Parses diagnostic notation as written by `to_diag`. Byte strings may
+/// also be written as `b64'...'`, and `/ comments /` are skipped.
+pub fn from_diag(text: &str) -> Result<Vec<u8>, Error> {
+    let mut parser = Parser {
+        s: text,
+        pos: 0,
+        out: Vec::new(),
+    };
+    parser.item(0)?;
+    parser.space();
+    if parser.pos != text.len() {
+        return Err(parser.error("trailing text"));
+    }
+    Ok(parser.out)
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+    out: Vec<u8>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, what: &str) -> Error {
+        Error::Message(format!("{} at {}", what, self.pos))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn space(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            match trimmed.strip_prefix('/').and_then(|c| c.find('/')) {
+                Some(end) => self.pos += end + 2,
+                None => return,
+            }
+        }
+    }
+
+    /// Skips `token` and any space before it, if it is next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.space();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", token)))
+        }
+    }
+
+    /// The longest run of characters matching `f`.
+    fn take(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        let len = self.rest().find(|c| !f(c)).unwrap_or(self.rest().len());
+        self.pos += len;
+        &self.s[start..self.pos]
+    }
+
+    /// An encoding indicator, `_0` to `_3`.
+    fn indicator(&mut self) -> Result<Option<u8>, Error> {
+        if !self.rest().starts_with('_') {
+            return Ok(None);
+        }
+        self.pos += 1;
+        match self.take(|c| c.is_ascii_digit()) {
+            "0" => Ok(Some(0)),
+            "1" => Ok(Some(1)),
+            "2" => Ok(Some(2)),
+            "3" => Ok(Some(3)),
+            _ => Err(self.error("bad encoding indicator")),
+        }
+    }
+
+    fn head(&mut self, major: u8, n: u64, indicator: Option<u8>) -> Result<(), Error> {
+        let info = indicator.map_or(shortest_info(n), |i| 24 + i);
+        if info < 24 {
+            self.out.push(major << 5 | info);
+            return Ok(());
+        }
+        let len = 1 << (info - 24);
+        if len < 8 && n >> (8 * len) != 0 {
+            return Err(self.error("value does not fit its encoding indicator"));
+        }
+        self.out.push(major << 5 | info);
+        self.out.extend_from_slice(&n.to_be_bytes()[8 - len..]);
+        Ok(())
+    }
+
+    fn item(&mut self, depth: usize) -> Result<(), Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        self.space();
+        match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                self.items(4, ']', depth)
+            }
+            Some('{') => {
+                self.pos += 1;
+                self.items(5, '}', depth)
+            }
+            Some('(') => {
+                self.pos += 1;
+                self.expect("_")?;
+                self.chunks(depth)
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(depth),
+            _ => {
+                if self.string()? {
+                    return Ok(());
+                }
+                let word = self.take(|c| c.is_ascii_alphabetic()).to_owned();
+                match word.as_str() {
+                    "false" => self.out.push(0xf4),
+                    "true" => self.out.push(0xf5),
+                    "null" => self.out.push(0xf6),
+                    "undefined" => self.out.push(0xf7),
+                    "NaN" if self.rest().starts_with('\'') => {
+                        self.pos += 1;
+                        self.nan_bits()?;
+                    }
+                    "NaN" | "Infinity" => {
+                        let indicator = self.indicator()?;
+                        self.float(&word, indicator)?;
+                    }
+                    "simple" => {
+                        self.expect("(")?;
+                        self.space();
+                        let n = self.take(|c| c.is_ascii_digit());
+                        let n = n.parse::<u8>().ok().filter(|n| !(24..32).contains(n));
+                        let n = n.ok_or_else(|| self.error("bad simple value"))?;
+                        self.head(7, n.into(), None)?;
+                        self.expect(")")?;
+                    }
+                    _ => return Err(self.error("unexpected input")),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The items of an array, major type 4, or the entries of a map,
+    /// major type 5, after the opening bracket.
+    fn items(&mut self, major: u8, close: char, depth: usize) -> Result<(), Error> {
+        let indefinite = self.eat("_");
+        let start = self.out.len();
+        let mut len = 0u64;
+        self.space();
+        if !self.rest().starts_with(close) {
+            loop {
+                self.item(depth + 1)?;
+                if major == 5 {
+                    self.expect(":")?;
+                    self.item(depth + 1)?;
+                }
+                len += 1;
+                if !self.eat(",") {
+                    break;
+                }
+            }
+        }
+        self.expect(&close.to_string())?;
+        if indefinite {
+            self.out.insert(start, major << 5 | 31);
+            self.out.push(0xff);
+        } else {
+            // the items are written first, their count goes in front of them
+            let items = self.out.split_off(start);
+            self.head(major, len, None)?;
+            self.out.extend_from_slice(&items);
+        }
+        Ok(())
+    }
+
+    /// The chunks of an indefinite length string, after `(_`.
+    fn chunks(&mut self, depth: usize) -> Result<(), Error> {
+        let start = self.out.len();
+        let mut major = None;
+        loop {
+            let chunk = self.out.len();
+            self.item(depth + 1)?;
+            let chunk_major = self.out[chunk] >> 5;
+            let definite = self.out[chunk] & 0x1f != 31;
+            if !definite
+                || chunk_major != 2 && chunk_major != 3
+                || major.is_some() && major != Some(chunk_major)
+            {
+                return Err(self.error("chunks must be strings of one kind"));
+            }
+            major = Some(chunk_major);
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+        self.out.insert(start, major.unwrap_or(2) << 5 | 31);
+        self.out.push(0xff);
+        Ok(())
+    }
+
+    /// A text or byte string, if one is next.
+    fn string(&mut self) -> Result<bool, Error> {
+        if self.rest().starts_with('"') {
+            // find the closing quote, skipping escaped characters
+            let mut end = None;
+            let mut escaped = false;
+            for (i, c) in self.rest().char_indices().skip(1) {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => {
+                        end = Some(i);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let end = end.ok_or_else(|| self.error("unterminated string"))?;
+            let text: String =
+                serde_json::from_str(&self.rest()[..=end]).map_err(|_| self.error("bad string"))?;
+            self.pos += end + 1;
+            self.head(3, text.len() as u64, None)?;
+            self.out.extend_from_slice(text.as_bytes());
+            return Ok(true);
+        }
+        let bytes = if self.rest().starts_with("h'") {
+            self.pos += 2;
+            let hex: String = self.quoted()?.split_whitespace().collect();
+            if hex.len() & 1 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(self.error("bad hex string"));
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect::<Vec<u8>>()
+        } else if self.rest().starts_with("b64'") {
+            self.pos += 4;
+            let b64 = self.quoted()?.to_owned();
+            base64::decode(&b64)
+                .or_else(|_| base64::decode_config(&b64, base64::URL_SAFE_NO_PAD))
+                .map_err(|_| self.error("bad base64 string"))?
+        } else {
+            return Ok(false);
+        };
+        self.head(2, bytes.len() as u64, None)?;
+        self.out.extend_from_slice(&bytes);
+        Ok(true)
+    }
+
+    /// The text up to the next `'`, skipping it.
+    fn quoted(&mut self) -> Result<&str, Error> {
+        let len = self
+            .rest()
+            .find('\'')
+            .ok_or_else(|| self.error("unterminated string"))?;
+        let start = self.pos;
+        self.pos += len + 1;
+        Ok(&self.s[start..start + len])
+    }
+
+    /// An integer, a float, or a tag number and its item.
+    fn number(&mut self, depth: usize) -> Result<(), Error> {
+        let start = self.pos;
+        let negative = self.eat("-");
+        if self.rest().starts_with("Infinity") {
+            self.pos += "Infinity".len();
+            let indicator = self.indicator()?;
+            return self.float("-Infinity", indicator);
+        }
+        self.take(|c| c.is_ascii_digit());
+        let is_float = self.rest().starts_with('.')
+            || self.rest().starts_with('e')
+            || self.rest().starts_with('E');
+        if is_float {
+            self.take(|c| c.is_ascii_digit() || ".eE+-".contains(c));
+            let number = self.s[start..self.pos].to_owned();
+            let indicator = self.indicator()?;
+            return self.float(&number, indicator);
+        }
+        let digits = &self.s[start + negative as usize..self.pos];
+        let n: u64 = digits.parse().map_err(|_| self.error("bad integer"))?;
+        let indicator = self.indicator()?;
+        if negative {
+            if n == 0 {
+                return Err(self.error("-0 is not an integer"));
+            }
+            self.head(1, n - 1, indicator)
+        } else if self.rest().starts_with('(') {
+            self.pos += 1;
+            self.head(6, n, indicator)?;
+            self.item(depth + 1)?;
+            self.expect(")")
+        } else {
+            self.head(0, n, indicator)
+        }
+    }
+
+    fn float(&mut self, number: &str, indicator: Option<u8>) -> Result<(), Error> {
+        let n: f64 = number.parse().map_err(|_| self.error("bad float"))?;
+        match indicator {
+            None => {
+                let _ = encode_float(n, &mut Encoder::new(IoWrite(&mut self.out)));
+            }
+            Some(1) => {
+                let half = half::f16::from_f64(n);
+                if f64::from(half) != n && !n.is_nan() {
+                    return Err(self.error("value does not fit its encoding indicator"));
+                }
+                self.out.push(0xf9);
+                self.out.extend_from_slice(&half.to_bits().to_be_bytes());
+            }
+            Some(2) => {
+                let single = n as f32;
+                if f64::from(single) != n && !n.is_nan() {
+                    return Err(self.error("value does not fit its encoding indicator"));
+                }
+                self.out.push(0xfa);
+                self.out.extend_from_slice(&single.to_bits().to_be_bytes());
+            }
+            Some(3) => {
+                self.out.push(0xfb);
+                self.out.extend_from_slice(&n.to_bits().to_be_bytes());
+            }
+            _ => return Err(self.error("bad encoding indicator for a float")),
+        }
+        Ok(())
+    }
+
+    /// The bits of a NaN, `7e01'` after `NaN'`, their length giving the
+    /// float's width.
+    fn nan_bits(&mut self) -> Result<(), Error> {
+        let hex = self.quoted()?;
+        let bits = Some(hex)
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok());
+        let (initial, is_nan) = match (hex.len(), bits) {
+            (4, Some(bits)) => (0xf9, half::f16::from_bits(bits as u16).is_nan()),
+            (8, Some(bits)) => (0xfa, f32::from_bits(bits as u32).is_nan()),
+            (16, Some(bits)) => (0xfb, f64::from_bits(bits).is_nan()),
+            _ => (0, false),
+        };
+        let len = hex.len() / 2;
+        match bits {
+            Some(bits) if is_nan => {
+                self.out.push(initial);
+                self.out.extend_from_slice(&bits.to_be_bytes()[8 - len..]);
+                Ok(())
+            }
+            _ => Err(self.error("bad NaN")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // RFC 8949, appendix A, and a few items that are not in their shortest
+    // form
+    const CASES: &[(&str, &str)] = &[
+        ("00", "0"),
+        ("17", "23"),
+        ("1818", "24"),
+        ("1903e8", "1000"),
+        ("1bffffffffffffffff", "18446744073709551615"),
+        ("20", "-1"),
+        ("3903e7", "-1000"),
+        ("3b7fffffffffffffff", "-9223372036854775808"),
+        ("f90000", "0.0"),
+        ("f98000", "-0.0"),
+        ("f93e00", "1.5"),
+        ("fa47c35000", "100000.0"),
+        ("fb3ff199999999999a", "1.1"),
+        ("fb7e37e43c8800759c", "1e300"),
+        ("f90001", "5.960464477539063e-8"),
+        ("f97c00", "Infinity"),
+        ("f97e00", "NaN"),
+        ("f9fc00", "-Infinity"),
+        ("f4", "false"),
+        ("f5", "true"),
+        ("f6", "null"),
+        ("f7", "undefined"),
+        ("f0", "simple(16)"),
+        ("f8ff", "simple(255)"),
+        (
+            "c074323031332d30332d32315432303a30343a30305a",
+            "0(\"2013-03-21T20:04:00Z\")",
+        ),
+        (
+            "d82076687474703a2f2f7777772e6578616d706c652e636f6d",
+            "32(\"http://www.example.com\")",
+        ),
+        ("d9d9f7f6", "55799(null)"),
+        ("40", "h''"),
+        ("4401020304", "h'01020304'"),
+        ("60", "\"\""),
+        ("62225c", "\"\\\"\\\\\""),
+        ("63e6b0b4", "\"\u{6c34}\""),
+        ("80", "[]"),
+        ("8301820203820405", "[1, [2, 3], [4, 5]]"),
+        ("a201020304", "{1: 2, 3: 4}"),
+        ("a26161016162820203", "{\"a\": 1, \"b\": [2, 3]}"),
+        ("5f42010243030405ff", "(_ h'0102', h'030405')"),
+        ("7f657374726561646d696e67ff", "(_ \"strea\", \"ming\")"),
+        ("9fff", "[_ ]"),
+        ("9f018202039f0405ffff", "[_ 1, [2, 3], [_ 4, 5]]"),
+        ("bf61610161629f0203ffff", "{_ \"a\": 1, \"b\": [_ 2, 3]}"),
+        ("1800", "0_0"),
+        ("190001", "1_1"),
+        ("3a00000000", "-1_2"),
+        ("fa3fc00000", "1.5_2"),
+        ("fb3ff8000000000000", "1.5_3"),
+        ("fa3dcccccd", "0.10000000149011612"),
+        ("fa7f800000", "Infinity_2"),
+        ("fa7fc00000", "NaN_2"),
+        ("fb7ff8000000000000", "NaN_3"),
+        ("f97e01", "NaN'7e01'"),
+        ("f9fe00", "NaN'fe00'"),
+        ("fa7fc00001", "NaN'7fc00001'"),
+        ("fb7ff8000000000001", "NaN'7ff8000000000001'"),
+    ];
+
+    #[test]
+    fn test_to_diag() {
+        for (cbor, diag) in CASES {
+            assert_eq!(to_diag(&hex(cbor)).ok().as_deref(), Some(*diag), "{}", cbor);
+        }
+        assert!(to_diag(&hex("8201")).is_err());
+        assert!(to_diag(&hex("0101")).is_err());
+        assert!(to_diag(&hex("ff")).is_err());
+        assert!(to_diag(&[0x81; 1000]).is_err());
+    }
+
+    #[test]
+    fn test_from_diag() {
+        for (cbor, diag) in CASES {
+            assert_eq!(from_diag(diag).ok(), Some(hex(cbor)), "{}", diag);
+        }
+        assert_eq!(
+            from_diag(" { \"a\" : b64'AQID' , / a comment / 1: 1(-1.5_1) } ").unwrap(),
+            hex("a261614301020301c1f9be00")
+        );
+        assert_eq!(from_diag("h'01 02\n03'").unwrap(), hex("43010203"));
+        for bad in &[
+            "",
+            "[1, 2",
+            "{1}",
+            "[1] 2",
+            "-0",
+            "256_0",
+            "1.5_0",
+            "1.1_1",
+            "1.1_2",
+            "100000.0_1",
+            "NaN_0",
+            "NaN'7c00'",
+            "NaN'7e0'",
+            "NaN'+7e01'",
+            "h'123'",
+            "(_ 1)",
+            "(_ h'01', \"a\")",
+            "simple(24)",
+            "\"abc",
+            "nope",
+        ] {
+            assert!(from_diag(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::{fmt, io};
+
+use minicbor::{decode, encode};
+
+/// Error of `ser::to_vec`, `de::from_slice` and `diag::from_diag`.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(decode::Error),
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Decode(e) => write!(f, "{}", e),
+            Error::Message(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl From<encode::Error<io::Error>> for Error {
+    fn from(e: encode::Error<io::Error>) -> Self {
+        match e {
+            encode::Error::Write(e) => Error::Io(e),
+            e => Error::Message(e.to_string()),
+        }
+    }
+}
+
+impl From<decode::Error> for Error {
+    fn from(e: decode::Error) -> Self {
+        Error::Decode(e)
+    }
+}
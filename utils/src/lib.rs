@@ -1,167 +1,365 @@
+pub mod de;
+pub mod diag;
+mod error;
+pub mod protocol;
+pub mod ser;
+mod value;
+
+pub use error::Error;
+pub use value::Cbor;
+
+use std::{convert::TryFrom, io};
+
 use minicbor::{
-    decode::{self, Tokenizer},
+    decode,
     encode::{self, write::EndOfSlice, Encode, Encoder},
 };
 use serde_json::{Map, Value};
 
+// CBOR without a JSON counterpart is written as an object with `$` keys:
+// `{"$bytes": base64}` and `{"$tag": n, "value": v}`. Standard tags get a key
+// of their own, see `STANDARD_TAGS`. Map keys starting with `$` are escaped,
+// see `map_key`, so no map reads as one of these.
+const BYTES_KEY: &str = "$bytes";
+const TAG_KEY: &str = "$tag";
+const VALUE_KEY: &str = "value";
+const SELF_DESCRIBED: u64 = 55799;
+
+// RFC 8949 section 3.4. Date strings and URIs keep their text, epoch times
+// their number. Bignums are written in decimal, e.g. `"-257"` for 3(h'0100'),
+// decimal fractions as `"27315e-2"` and bigfloats as `"0x3p-1"`.
+const STANDARD_TAGS: &[(u64, &str)] = &[
+    (0, "$datetime"),
+    (1, "$epoch"),
+    (2, "$bignum"),
+    (3, "$bignum"),
+    (4, "$decimal"),
+    (5, "$bigfloat"),
+    (32, "$uri"),
+    (33, "$base64url"),
+    (34, "$base64"),
+    (35, "$regex"),
+    (36, "$mime"),
+];
+
+// Longer bignums stay `$tag`, as converting them takes quadratic time.
+const MAX_BIGNUM_LEN: usize = 64;
+
 pub fn decode_cbor(buf: &[u8]) -> Result<Value, decode::Error> {
-    let mut tokens = Tokenizer::new(buf);
-    decode_cbor_inner(&mut tokens)
-}
-
-fn decode_cbor_inner(tokenizer: &mut Tokenizer) -> Result<Value, decode::Error> {
-    Ok(match tokenizer.token()? {
-        decode::Token::Bool(b) => Value::from(b),
-        decode::Token::U8(n) => Value::from(n),
-        decode::Token::U16(n) => Value::from(n),
-        decode::Token::U32(n) => Value::from(n),
-        decode::Token::U64(n) => Value::from(n),
-        decode::Token::I8(n) => Value::from(n),
-        decode::Token::I16(n) => Value::from(n),
-        decode::Token::I32(n) => Value::from(n),
-        decode::Token::I64(n) => Value::from(n),
-        decode::Token::F16(n) => Value::from(n),
-        decode::Token::F32(n) => Value::from(n),
-        decode::Token::F64(n) => Value::from(n),
-        decode::Token::Bytes(b) => {
-            let mut buf = String::from("#");
-            base64::encode_config_buf(b, base64::STANDARD, &mut buf);
-            Value::String(buf)
-        }
-        decode::Token::String(s) => Value::from(s),
-        decode::Token::Array(n) => {
-            let mut result = Vec::new();
-            for _ in 0..n {
-                result.push(decode_cbor_inner(tokenizer)?);
-            }
-            Value::from(result)
+    Cbor::from_slice(buf).map(Value::from)
+}
+
+pub fn encode_cbor(value: &Value, buf: &mut [u8]) -> Result<usize, encode::Error<EndOfSlice>> {
+    let begin = buf as *const [u8] as *const () as usize;
+    let mut e = Encoder::new(buf);
+    Cbor::from(value).encode(&mut e)?;
+    let end = e.into_inner() as *const [u8] as *const () as usize;
+    Ok(end - begin)
+}
+
+/// Encodes `value` into a new `Vec`, which grows to fit it.
+pub fn encode_cbor_vec(value: &Value) -> Vec<u8> {
+    // JSON numbers always fit a CBOR integer or float
+    Cbor::from(value)
+        .to_vec()
+        .expect("writing to a Vec never fails")
+}
+
+/// Encodes `value` as deterministic CBOR, see `Cbor::to_deterministic_vec`.
+pub fn encode_cbor_deterministic(value: &Value) -> Result<Vec<u8>, encode::Error<io::Error>> {
+    Cbor::from(value).to_deterministic_vec()
+}
+
+/// Encodes `value` into any `std::io::Write`.
+pub fn encode_cbor_io<W: io::Write>(
+    value: &Value,
+    writer: W,
+) -> Result<(), encode::Error<io::Error>> {
+    Cbor::from(value).encode(&mut Encoder::new(IoWrite(writer)))
+}
+
+pub(crate) struct IoWrite<W>(pub(crate) W);
+
+impl<W: io::Write> encode::Write for IoWrite<W> {
+    type Error = io::Error;
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+        self.0.write_all(buf)
+    }
+}
+
+/// The JSON form of a CBOR value. Every tag is kept, so that the value
+/// converts back to the same CBOR, except the self-described CBOR tag, which
+/// only marks data as CBOR. Map keys become strings, see `map_key`.
+impl From<Cbor> for Value {
+    fn from(cbor: Cbor) -> Self {
+        match cbor {
+            Cbor::Null => Value::Null,
+            Cbor::Bool(b) => Value::Bool(b),
+            Cbor::Integer(n) => integer(n).unwrap_or_else(|| Value::from(n as f64)),
+            Cbor::Float(n) => Value::from(n),
+            Cbor::Bytes(bytes) => wrap(BYTES_KEY, Value::String(base64::encode(bytes))),
+            Cbor::Text(s) => Value::String(s),
+            Cbor::Array(a) => Value::Array(a.into_iter().map(Value::from).collect()),
+            Cbor::Map(m) => Value::Object(
+                m.into_iter()
+                    .map(|(k, v)| (map_key(k), Value::from(v)))
+                    .collect(),
+            ),
+            Cbor::Tag(SELF_DESCRIBED, value) => Value::from(*value),
+            Cbor::Tag(tag, value) => standard_tag(tag, *value).unwrap_or_else(|value| {
+                let mut result = Map::new();
+                result.insert(TAG_KEY.to_owned(), Value::from(tag));
+                result.insert(VALUE_KEY.to_owned(), Value::from(value));
+                Value::Object(result)
+            }),
         }
-        decode::Token::Map(m) => {
-            let mut result = Map::new();
-            for _ in 0..m {
-                if let Some(s) = decode_cbor_inner(tokenizer)?.as_str() {
-                    result.insert(s.to_owned(), decode_cbor_inner(tokenizer)?);
+    }
+}
+
+/// The CBOR value a JSON value stands for, the inverse of `From<Cbor>`.
+impl From<&Value> for Cbor {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => Cbor::Null,
+            Value::Bool(b) => Cbor::Bool(*b),
+            Value::Number(n) => {
+                if let Some(n) = n.as_u64() {
+                    Cbor::from(n)
+                } else if let Some(n) = n.as_i64() {
+                    Cbor::from(n)
+                } else {
+                    n.as_f64().map_or(Cbor::Null, Cbor::Float)
                 }
             }
-            Value::from(result)
-        }
-        decode::Token::Tag(_t) => return Err(decode::Error::Message("Tag not yet supported")),
-        decode::Token::Simple(_s) => {
-            return Err(decode::Error::Message("Simple not yet supported"))
-        }
-        decode::Token::Break => return Err(decode::Error::Message("unexpected break")),
-        decode::Token::Null => Value::Null,
-        decode::Token::Undefined => Value::Null,
-        decode::Token::BeginBytes => {
-            let mut bytes = Vec::new();
-            loop {
-                let token = tokenizer.token()?;
-                if token == decode::Token::Break {
-                    break;
-                }
-                if let decode::Token::Bytes(b) = token {
-                    bytes.extend_from_slice(b);
+            Value::String(s) => Cbor::Text(s.clone()),
+            Value::Array(a) => Cbor::Array(a.iter().map(Cbor::from).collect()),
+            Value::Object(o) => {
+                if let Some(bytes) = as_bytes(o) {
+                    Cbor::Bytes(bytes)
+                } else if let Some((tag, value)) = as_tagged(o) {
+                    Cbor::Tag(tag, Box::new(Cbor::from(value)))
+                } else if let Some(tagged) = as_standard_tag(o) {
+                    tagged
+                } else {
+                    Cbor::Map(o.iter().map(|(k, v)| (key(k), Cbor::from(v))).collect())
                 }
             }
-            let mut buf = String::from("#");
-            base64::encode_config_buf(bytes, base64::STANDARD, &mut buf);
-            Value::String(buf)
         }
-        decode::Token::BeginString => {
-            let mut buf = String::new();
-            loop {
-                let token = tokenizer.token()?;
-                if token == decode::Token::Break {
-                    break;
-                }
-                if let decode::Token::String(s) = token {
-                    buf.push_str(s);
-                }
+    }
+}
+
+fn integer(n: i128) -> Option<Value> {
+    u64::try_from(n)
+        .map(Value::from)
+        .or_else(|_| i64::try_from(n).map(Value::from))
+        .ok()
+}
+
+fn wrap(key: &str, value: Value) -> Value {
+    let mut result = Map::new();
+    result.insert(key.to_owned(), value);
+    Value::Object(result)
+}
+
+fn as_bytes(o: &Map<String, Value>) -> Option<Vec<u8>> {
+    if o.len() != 1 {
+        return None;
+    }
+    base64::decode(o.get(BYTES_KEY)?.as_str()?).ok()
+}
+
+fn as_tagged(o: &Map<String, Value>) -> Option<(u64, &Value)> {
+    if o.len() != 2 {
+        return None;
+    }
+    Some((o.get(TAG_KEY)?.as_u64()?, o.get(VALUE_KEY)?))
+}
+
+/// JSON keys are strings, so any other key is written as `$` and the JSON
+/// text of its JSON form, e.g. `1` as `"$1"` and `h'01'` as
+/// `"${\"$bytes\":\"AQ==\"}"`. A text key starting with `$` gets another
+/// one, e.g. `"$1"` as `"$$1"`; every other text key is kept as it is.
+fn map_key(k: Cbor) -> String {
+    match k {
+        Cbor::Text(s) if s.starts_with('$') => format!("${}", s),
+        Cbor::Text(s) => s,
+        k => format!("${}", Value::from(k)),
+    }
+}
+
+/// The CBOR key `map_key` wrote as `k`.
+fn key(k: &str) -> Cbor {
+    match k.strip_prefix('$') {
+        Some(rest) if rest.starts_with('$') => Cbor::Text(rest.to_owned()),
+        Some(rest) => match serde_json::from_str::<Value>(rest) {
+            // only exactly what `map_key` writes, so other keys stay text
+            Ok(value)
+                if !value.is_string()
+                    && serde_json::to_string(&value).ok().as_deref() == Some(rest) =>
+            {
+                Cbor::from(&value)
             }
-            Value::String(buf)
-        }
-        decode::Token::BeginArray => {
-            let mut buf = Vec::new();
-            loop {
-                let mut look_ahead = tokenizer.clone();
-                let token = look_ahead.token()?;
-                if token == decode::Token::Break {
-                    tokenizer.token()?;
-                    break;
-                } else {
-                    buf.push(decode_cbor_inner(tokenizer)?)
-                }
+            _ => Cbor::Text(k.to_owned()),
+        },
+        None => Cbor::Text(k.to_owned()),
+    }
+}
+
+/// The escape of a standard tag, see `STANDARD_TAGS`. Gives `value` back if
+/// it is not what the tag expects, or would not convert back to the same
+/// CBOR.
+fn standard_tag(tag: u64, value: Cbor) -> Result<Value, Cbor> {
+    let key = match STANDARD_TAGS.iter().find(|(t, _)| *t == tag) {
+        Some((_, key)) => key,
+        None => return Err(value),
+    };
+    let escaped = match (tag, &value) {
+        (0 | 32..=36, Cbor::Text(s)) => Some(Value::String(s.clone())),
+        (1, Cbor::Integer(n)) => integer(*n),
+        (1, Cbor::Float(n)) if n.is_finite() => Some(Value::from(*n)),
+        (2 | 3, Cbor::Bytes(bytes)) => bignum_to_string(bytes, tag == 3).map(Value::String),
+        (4 | 5, Cbor::Array(a)) => match a.as_slice() {
+            [Cbor::Integer(e), Cbor::Integer(m)] => {
+                Some(Value::String(fraction_to_string(tag, *e, *m)))
             }
-            Value::Array(buf)
+            _ => None,
+        },
+        _ => None,
+    };
+    escaped.map(|escaped| wrap(key, escaped)).ok_or(value)
+}
+
+fn as_standard_tag(o: &Map<String, Value>) -> Option<Cbor> {
+    if o.len() != 1 {
+        return None;
+    }
+    let (key, value) = o.iter().next()?;
+    let (tag, _) = STANDARD_TAGS.iter().find(|(_, k)| k == key)?;
+    let (tag, content) = match (*tag, value) {
+        (0 | 32..=36, Value::String(s)) => (*tag, Cbor::Text(s.clone())),
+        (1, Value::Number(_)) => (1, Cbor::from(value)),
+        (2, Value::String(s)) => parse_bignum(s)?,
+        (4 | 5, Value::String(s)) => (*tag, parse_fraction(*tag, s)?),
+        _ => return None,
+    };
+    Some(Cbor::Tag(tag, Box::new(content)))
+}
+
+/// The decimal digits of a bignum, whose `bytes` are `n` for tag 2, or
+/// `-1 - n` if `negative`, for tag 3. `None` for bytes with a leading zero,
+/// which would not convert back to the same bytes.
+fn bignum_to_string(bytes: &[u8], negative: bool) -> Option<String> {
+    if bytes.first() == Some(&0) || bytes.len() > MAX_BIGNUM_LEN {
+        return None;
+    }
+    // little-endian base 10^9 limbs
+    let mut limbs = vec![0u32];
+    let add = |limbs: &mut Vec<u32>, mul: u64, mut carry: u64| {
+        for limb in limbs.iter_mut() {
+            let n = u64::from(*limb) * mul + carry;
+            *limb = (n % 1_000_000_000) as u32;
+            carry = n / 1_000_000_000;
         }
-        decode::Token::BeginMap => {
-            let mut buf = Map::new();
-            loop {
-                let mut look_ahead = tokenizer.clone();
-                let token = look_ahead.token()?;
-                if token == decode::Token::Break {
-                    tokenizer.token()?;
-                    break;
-                } else if let Some(s) = decode_cbor_inner(tokenizer)?.as_str() {
-                    buf.insert(s.to_owned(), decode_cbor_inner(tokenizer)?);
-                }
-            }
-            Value::Object(buf)
+        if carry > 0 {
+            limbs.push(carry as u32);
         }
-    })
-}
-pub fn encode_cbor(value: &Value, buf: &mut [u8]) -> Result<usize, encode::Error<EndOfSlice>> {
-    let begin = buf as *const [u8] as *const () as usize;
-    let mut e = Encoder::new(buf);
-    encode_cbor_inner(value, &mut e)?;
-    let end = e.into_inner() as *const [u8] as *const () as usize;
-    Ok(end - begin)
+    };
+    for b in bytes {
+        add(&mut limbs, 256, u64::from(*b));
+    }
+    if negative {
+        add(&mut limbs, 1, 1);
+    }
+    let mut s = if negative {
+        "-".to_owned()
+    } else {
+        String::new()
+    };
+    let mut limbs = limbs.iter().rev();
+    s.push_str(&limbs.next()?.to_string());
+    for limb in limbs {
+        s.push_str(&format!("{:09}", limb));
+    }
+    Some(s)
 }
-fn encode_cbor_inner<W: encode::Write>(
-    value: &Value,
-    e: &mut Encoder<W>,
-) -> Result<(), encode::Error<W::Error>> {
-    match value {
-        Value::Null => {
-            e.null()?;
+
+/// The tag and bytes of a bignum written by `bignum_to_string`.
+fn parse_bignum(s: &str) -> Option<(u64, Cbor)> {
+    let (tag, digits) = match s.strip_prefix('-') {
+        Some(digits) => (3, digits),
+        None => (2, s),
+    };
+    let canonical = digits == "0" || !digits.starts_with('0');
+    if digits.is_empty() || !canonical || digits.len() > 3 * MAX_BIGNUM_LEN {
+        return None;
+    }
+    // big-endian, without leading zeros
+    let mut bytes: Vec<u8> = Vec::new();
+    for d in digits.chars() {
+        let mut carry = d.to_digit(10)?;
+        for b in bytes.iter_mut().rev() {
+            let n = u32::from(*b) * 10 + carry;
+            *b = n as u8;
+            carry = n >> 8;
         }
-        Value::Bool(b) => {
-            e.bool(*b)?;
+        if carry > 0 {
+            bytes.insert(0, carry as u8);
         }
-        Value::Number(n) => {
-            if let Some(n) = n.as_f64() {
-                e.f64(n)?;
-            } else if let Some(n) = n.as_i64() {
-                e.i64(n)?;
-            } else {
-                e.u64(n.as_u64().unwrap())?;
-            }
+    }
+    if tag == 3 {
+        // `-1 - n`, so the bytes hold the magnitude less one
+        if bytes.is_empty() {
+            return None;
         }
-        Value::String(s) => {
-            if s.starts_with('#') {
-                match base64::decode(s.split_once('#').unwrap().1) {
-                    Ok(bytes) => e.bytes(&bytes)?,
-                    Err(_) => e.str(s)?,
-                };
-            } else {
-                e.str(s)?;
+        for b in bytes.iter_mut().rev() {
+            let borrow = *b == 0;
+            *b = b.wrapping_sub(1);
+            if !borrow {
+                break;
             }
         }
-        Value::Array(a) => {
-            e.array(a.len() as u64)?;
-            for element in a {
-                encode_cbor_inner(element, e)?;
-            }
+        if bytes[0] == 0 {
+            bytes.remove(0);
         }
-        Value::Object(o) => {
-            e.map(o.len() as u64)?;
-            for (k, v) in o {
-                k.encode(e)?;
-                encode_cbor_inner(v, e)?;
-            }
+    }
+    if bytes.len() > MAX_BIGNUM_LEN {
+        return None;
+    }
+    Some((tag, Cbor::Bytes(bytes)))
+}
+
+/// `[e, m]` of tag 4 as `"me"`, or of tag 5 as `"0xmpe"` with a hexadecimal
+/// mantissa.
+fn fraction_to_string(tag: u64, e: i128, m: i128) -> String {
+    if tag == 4 {
+        format!("{}e{}", m, e)
+    } else {
+        let sign = if m < 0 { "-" } else { "" };
+        format!("{}0x{:x}p{}", sign, m.unsigned_abs(), e)
+    }
+}
+
+/// The `[e, m]` array of a fraction written by `fraction_to_string`.
+fn parse_fraction(tag: u64, s: &str) -> Option<Cbor> {
+    let (m, e) = if tag == 4 {
+        let (m, e) = s.split_once('e')?;
+        (m.parse().ok()?, e)
+    } else {
+        let (sign, rest) = match s.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, s),
+        };
+        let (m, e) = rest.strip_prefix("0x")?.split_once('p')?;
+        if !m.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
         }
+        (sign * i128::from_str_radix(m, 16).ok()?, e)
     };
-    Ok(())
+    let e: i128 = e.parse().ok()?;
+    // only these convert back to a CBOR integer
+    integer(m)?;
+    integer(e)?;
+    Some(Cbor::Array(vec![Cbor::Integer(e), Cbor::Integer(m)]))
 }
 
 #[test]
@@ -176,19 +374,27 @@ fn test_json_cbor() -> Result<(), decode::Error> {
            },
            "k3": ["a", "b", "c"],
        },
-       "b": "#RHVubg=="
+       "b": {"$bytes": "RHVubg=="},
+       "c": "#RHVubg==",
+       "d": {"$$bytes": "RHVubg=="},
     });
 
-    let mut buf = [0u8; 100];
+    let mut buf = [0u8; 150];
     let written = encode_cbor(&value, &mut buf).unwrap();
 
     let svalue: Value = decode_cbor(&buf[..written]).unwrap();
     assert_eq!(value, svalue);
 
-    let mut t = Tokenizer::new(&buf[..written]);
-    assert_eq!(t.token()?, decode::Token::Map(3));
+    let mut t = decode::Tokenizer::new(&buf[..written]);
+    assert_eq!(t.token()?, decode::Token::Map(5));
     assert_eq!(t.token()?, decode::Token::String("b"));
     assert_eq!(t.token()?, decode::Token::Bytes(b"Dunn"));
+    assert_eq!(t.token()?, decode::Token::String("c"));
+    assert_eq!(t.token()?, decode::Token::String("#RHVubg=="));
+    assert_eq!(t.token()?, decode::Token::String("d"));
+    assert_eq!(t.token()?, decode::Token::Map(1));
+    assert_eq!(t.token()?, decode::Token::String("$bytes"));
+    assert_eq!(t.token()?, decode::Token::String("RHVubg=="));
     Ok(())
 }
 
@@ -225,3 +431,269 @@ fn test_indef_type() -> Result<(), encode::Error<EndOfSlice>> {
     assert_eq!(result, expected);
     Ok(())
 }
+
+#[test]
+fn test_number_encoding() {
+    let cases: &[(Value, &[u8])] = &[
+        (serde_json::json!(0), &[0x00]),
+        (serde_json::json!(23), &[0x17]),
+        (serde_json::json!(24), &[0x18, 0x18]),
+        (serde_json::json!(255), &[0x18, 0xff]),
+        (serde_json::json!(256), &[0x19, 0x01, 0x00]),
+        (serde_json::json!(65535), &[0x19, 0xff, 0xff]),
+        (serde_json::json!(65536), &[0x1a, 0x00, 0x01, 0x00, 0x00]),
+        (serde_json::json!(u32::MAX), &[0x1a, 0xff, 0xff, 0xff, 0xff]),
+        (
+            serde_json::json!(u64::from(u32::MAX) + 1),
+            &[0x1b, 0, 0, 0, 0x01, 0, 0, 0, 0],
+        ),
+        (
+            serde_json::json!((1u64 << 53) + 1),
+            &[0x1b, 0, 0x20, 0, 0, 0, 0, 0, 0x01],
+        ),
+        (
+            serde_json::json!(u64::MAX),
+            &[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+        ),
+        (serde_json::json!(-1), &[0x20]),
+        (serde_json::json!(-24), &[0x37]),
+        (serde_json::json!(-25), &[0x38, 0x18]),
+        (serde_json::json!(-256), &[0x38, 0xff]),
+        (serde_json::json!(-257), &[0x39, 0x01, 0x00]),
+        (serde_json::json!(-65536), &[0x39, 0xff, 0xff]),
+        (serde_json::json!(-65537), &[0x3a, 0x00, 0x01, 0x00, 0x00]),
+        (
+            serde_json::json!(i64::MIN),
+            &[0x3b, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+        ),
+        (serde_json::json!(0.0), &[0xf9, 0x00, 0x00]),
+        (serde_json::json!(1.5), &[0xf9, 0x3e, 0x00]),
+        (serde_json::json!(65504.0), &[0xf9, 0x7b, 0xff]),
+        (serde_json::json!(65536.0), &[0xfa, 0x47, 0x80, 0x00, 0x00]),
+        (serde_json::json!(100000.5), &[0xfa, 0x47, 0xc3, 0x50, 0x40]),
+        (
+            serde_json::json!(-4.1),
+            &[0xfb, 0xc0, 0x10, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66],
+        ),
+        (
+            serde_json::json!(1.0e300),
+            &[0xfb, 0x7e, 0x37, 0xe4, 0x3c, 0x88, 0x00, 0x75, 0x9c],
+        ),
+    ];
+    for (value, expected) in cases {
+        let mut buf = [0u8; 16];
+        let written = encode_cbor(value, &mut buf).unwrap();
+        assert_eq!(&buf[..written], *expected, "{}", value);
+        assert_eq!(&decode_cbor(&buf[..written]).unwrap(), value);
+    }
+}
+
+#[test]
+fn test_tags() {
+    let cases: &[(&[u8], Value)] = &[
+        // 0("2013-03-21T20:04:00Z"), 32("http://a.b"), 36("text/plain")
+        (
+            b"\xc0\x742013-03-21T20:04:00Z",
+            serde_json::json!({"$datetime": "2013-03-21T20:04:00Z"}),
+        ),
+        (
+            b"\xd8\x20\x6ahttp://a.b",
+            serde_json::json!({"$uri": "http://a.b"}),
+        ),
+        (
+            b"\xd8\x24\x6atext/plain",
+            serde_json::json!({"$mime": "text/plain"}),
+        ),
+        // 1(1363896240), 1(1363896240.5)
+        (
+            b"\xc1\x1a\x51\x4b\x67\xb0",
+            serde_json::json!({"$epoch": 1363896240}),
+        ),
+        (
+            b"\xc1\xfb\x41\xd4\x52\xd9\xec\x20\x00\x00",
+            serde_json::json!({"$epoch": 1363896240.5}),
+        ),
+        // 2(h'010000000000000000'), 3(h'010000000000000000'), 2(h''),
+        // 3(h''), 3(h'0100')
+        (
+            b"\xc2\x49\x01\x00\x00\x00\x00\x00\x00\x00\x00",
+            serde_json::json!({"$bignum": "18446744073709551616"}),
+        ),
+        (
+            b"\xc3\x49\x01\x00\x00\x00\x00\x00\x00\x00\x00",
+            serde_json::json!({"$bignum": "-18446744073709551617"}),
+        ),
+        (b"\xc2\x40", serde_json::json!({"$bignum": "0"})),
+        (b"\xc3\x40", serde_json::json!({"$bignum": "-1"})),
+        (b"\xc3\x42\x01\x00", serde_json::json!({"$bignum": "-257"})),
+        // 4([-2, 27315]), 4([2, -3]), 5([-1, 3]), 5([1, -255])
+        (
+            b"\xc4\x82\x21\x19\x6a\xb3",
+            serde_json::json!({"$decimal": "27315e-2"}),
+        ),
+        (b"\xc4\x82\x02\x22", serde_json::json!({"$decimal": "-3e2"})),
+        (
+            b"\xc5\x82\x20\x03",
+            serde_json::json!({"$bigfloat": "0x3p-1"}),
+        ),
+        (
+            b"\xc5\x82\x01\x38\xfe",
+            serde_json::json!({"$bigfloat": "-0xffp1"}),
+        ),
+        // content a standard tag does not expect, or that would not convert
+        // back to the same bytes: 2(h'0001'), 4(["a", 1]), 0(1), 1000({"a": 1})
+        (
+            b"\xc2\x42\x00\x01",
+            serde_json::json!({"$tag": 2, "value": {"$bytes": "AAE="}}),
+        ),
+        (
+            b"\xc4\x82\x61a\x01",
+            serde_json::json!({"$tag": 4, "value": ["a", 1]}),
+        ),
+        (b"\xc0\x01", serde_json::json!({"$tag": 0, "value": 1})),
+        (
+            b"\xd9\x03\xe8\xa1\x61a\x01",
+            serde_json::json!({"$tag": 1000, "value": {"a": 1}}),
+        ),
+    ];
+    for (cbor, expected) in cases {
+        let value = decode_cbor(cbor).unwrap();
+        assert_eq!(&value, expected);
+        assert_eq!(&encode_cbor_vec(&value), cbor, "{}", value);
+    }
+
+    // 55799(1) only marks the data as CBOR
+    assert_eq!(
+        decode_cbor(b"\xd9\xd9\xf7\x01").unwrap(),
+        serde_json::json!(1)
+    );
+
+    // escapes that no tag would be written as are maps
+    for value in &[
+        serde_json::json!({"$bignum": "-0"}),
+        serde_json::json!({"$bignum": "01"}),
+        serde_json::json!({"$decimal": "1.5"}),
+        serde_json::json!({"$bigfloat": "0x+1p0"}),
+        serde_json::json!({"$uri": 1}),
+    ] {
+        assert!(matches!(Cbor::from(value), Cbor::Map(_)), "{}", value);
+    }
+}
+
+#[test]
+fn test_map_keys() {
+    // {1: "a", -2: [true], h'01': 3, "b": 4}, and the same map indefinite
+    let definite = b"\xa4\x01\x61a\x21\x81\xf5\x41\x01\x03\x61b\x04";
+    let indefinite = b"\xbf\x01\x61a\x21\x81\xf5\x41\x01\x03\x61b\x04\xff";
+    let expected =
+        serde_json::json!({"$1": "a", "$-2": [true], "${\"$bytes\":\"AQ==\"}": 3, "b": 4});
+    assert_eq!(decode_cbor(definite).unwrap(), expected);
+    assert_eq!(decode_cbor(indefinite).unwrap(), expected);
+
+    // keys are ordered by their JSON text
+    assert_eq!(
+        encode_cbor_vec(&expected),
+        b"\xa4\x21\x81\xf5\x01\x61a\x41\x01\x03\x61b\x04"
+    );
+
+    // text keys are kept, only a leading `$` is doubled
+    let value = serde_json::json!({
+        "1": 1, "null": 2, "\"1\"": 3, "$$1": 4, "$$map": 5, "$1.5": 6, "$null": 7,
+        "$01": 8, "$\"1\"": 9, "$ 1": 10,
+    });
+    let buf = encode_cbor_vec(&value);
+    let keys: Vec<Cbor> = match Cbor::from_slice(&buf).unwrap() {
+        Cbor::Map(entries) => entries.into_iter().map(|(k, _)| k).collect(),
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        keys,
+        [
+            Cbor::from("\"1\""),
+            Cbor::from("$ 1"),
+            Cbor::from("$\"1\""),
+            Cbor::from("$1"),
+            Cbor::from("$map"),
+            Cbor::from("$01"),
+            Cbor::from(1.5),
+            Cbor::Null,
+            Cbor::from("1"),
+            Cbor::from("null"),
+        ]
+    );
+
+    // a `$` key that is not what `map_key` writes comes back escaped
+    let value = serde_json::json!({"$01": 1});
+    let buf = encode_cbor_vec(&value);
+    assert_eq!(buf, b"\xa1\x63$01\x01");
+    assert_eq!(decode_cbor(&buf).unwrap(), serde_json::json!({"$$01": 1}));
+
+    // a map that reads as an escape only has escaped keys
+    let buf = b"\xa1\x66$bytes\x64AQ==";
+    let value = decode_cbor(buf).unwrap();
+    assert_eq!(value, serde_json::json!({"$$bytes": "AQ=="}));
+    assert_eq!(encode_cbor_vec(&value), buf);
+}
+
+#[test]
+fn test_encode_vec() {
+    let value = serde_json::json!({
+        "history": vec![serde_json::json!({"at": 1363896240, "state": "on"}); 1000],
+    });
+    let buf = encode_cbor_vec(&value);
+    assert!(buf.len() > 1024);
+    assert_eq!(decode_cbor(&buf).unwrap(), value);
+
+    let mut slice = [0u8; 64];
+    let written = encode_cbor(&serde_json::json!({"a": [1, 2]}), &mut slice).unwrap();
+    assert_eq!(
+        encode_cbor_vec(&serde_json::json!({"a": [1, 2]})),
+        &slice[..written]
+    );
+    assert!(encode_cbor(&value, &mut slice).is_err());
+}
+
+#[test]
+fn test_encode_deterministic() {
+    // the JSON map sorts "10" before "9", deterministic CBOR sorts 9 first
+    let value = serde_json::json!({"$9": 1.5, "$10": {"b": 0, "a": 1}, "9": 2});
+    let buf = encode_cbor_deterministic(&value).unwrap();
+    assert_eq!(
+        buf,
+        b"\xa3\x09\xf9\x3e\x00\x0a\xa2\x61a\x01\x61b\x00\x61\x39\x02"
+    );
+    assert_eq!(decode_cbor(&buf).unwrap(), value);
+}
+
+#[test]
+fn test_deep_nesting() {
+    let mut buf = vec![0x81u8; 100_000];
+    buf.push(0xf6);
+    assert!(decode_cbor(&buf).is_err());
+
+    let mut buf = vec![0x9fu8; 100_000];
+    buf.push(0xff);
+    assert!(decode_cbor(&buf).is_err());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn prop_decode_cbor_no_panic(buf in proptest::collection::vec(proptest::num::u8::ANY, 0..512)) {
+        let _ = decode_cbor(&buf);
+        let _ = Cbor::from_slice(&buf);
+        let _ = de::from_slice::<Value>(&buf);
+        let _ = diag::to_diag(&buf);
+    }
+
+    #[test]
+    fn prop_bignum_roundtrip(
+        bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..MAX_BIGNUM_LEN),
+        negative: bool,
+    ) {
+        let bytes = &bytes[bytes.iter().take_while(|b| **b == 0).count()..];
+        let tag = if negative { 3 } else { 2 };
+        let s = bignum_to_string(bytes, negative).unwrap();
+        proptest::prop_assert_eq!(parse_bignum(&s), Some((tag, Cbor::Bytes(bytes.to_vec()))));
+    }
+}
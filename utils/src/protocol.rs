@@ -0,0 +1,60 @@
+//! Framing shared by the hub and its clients.
+//!
+//! Clients connect to `FRAMED_PATH`, where the first binary message of a
+//! connection starts with one byte naming the Noise pattern, followed by the
+//! first handshake message.
+//!
+//! Devices from before the kind byte connect to `LEGACY_PATH` and send a bare
+//! IX message, so the path alone tells the two framings apart.
+//!
+//! After the handshake every message is a Noise transport message carrying
+//! CBOR. An empty payload is never valid CBOR, so it is used as the rekey
+//! marker: the sender rekeys its outgoing cipher right after sending it and
+//! the receiver rekeys its incoming cipher right after reading it.
+//!
+//! `HANDSHAKE_IX_HFS` runs IX with an additional ML-KEM-768 exchange, so the
+//! session stays confidential even if X25519 is broken later. Its messages
+//! are much larger than plain IX ones. `HANDSHAKE_IK_HFS` does the same for
+//! IK, for clients that already pinned the hub key.
+//!
+//! A device that already completed a handshake may resume instead: the kind
+//! byte `HANDSHAKE_RESUME` is followed by its 32-byte public key and the next
+//! transport message of the old session. The hub keeps its half of a session
+//! as a ticket when the connection closes and accepts each ticket once. A
+//! resume that fails, e.g. because a message was lost when the connection
+//! dropped, still spends the ticket; the device then runs a full handshake.
+
+use serde::{Deserialize, Serialize};
+
+pub const HANDSHAKE_IX: u8 = 0;
+pub const HANDSHAKE_IK: u8 = 1;
+pub const HANDSHAKE_RESUME: u8 = 2;
+pub const HANDSHAKE_IX_HFS: u8 = 3;
+pub const HANDSHAKE_IK_HFS: u8 = 4;
+
+pub const FRAMED_PATH: &str = "/ws/v1";
+pub const LEGACY_PATH: &str = "/ws";
+
+pub const REKEY_AFTER_MESSAGES: u64 = 1 << 16;
+pub const REKEY_AFTER_MINUTES: u64 = 60;
+
+/// Sent by the browser for each line typed into its text field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextMessage {
+    pub text: String,
+}
+
+/// Short, human comparable form of a Noise handshake hash, e.g.
+/// `3fa1-09cc-71be-e402`. Both ends of a session compute the same value.
+pub fn fingerprint(handshake_hash: &[u8]) -> String {
+    handshake_hash
+        .chunks(2)
+        .take(4)
+        .map(|pair| {
+            pair.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
@@ -0,0 +1,439 @@
+//! A CBOR data item as its own type.
+//!
+//! Unlike a JSON value it tells text from bytes and integers from floats,
+//! and its maps take any key. Indefinite lengths are read but not kept.
+
+use std::{convert::TryFrom, io};
+
+use minicbor::{
+    data::{self, Tag, Type},
+    decode::{self, Decode, Decoder},
+    encode::{self, Encode, Encoder},
+};
+
+use crate::IoWrite;
+
+// Deeper input would be decoded recursively until the stack overflows.
+pub(crate) const MAX_DEPTH: usize = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cbor {
+    /// `null`, and `undefined` when decoding.
+    Null,
+    Bool(bool),
+    /// Only values within `i64` or `u64` can be encoded.
+    Integer(i128),
+    Float(f64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+    Tag(u64, Box<Cbor>),
+}
+
+impl Cbor {
+    pub fn from_slice(buf: &[u8]) -> Result<Self, decode::Error> {
+        decode_inner(&mut Decoder::new(buf), 0)
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, encode::Error<io::Error>> {
+        let mut buf = Vec::new();
+        self.encode(&mut Encoder::new(IoWrite(&mut buf)))?;
+        Ok(buf)
+    }
+
+    /// Encodes following the core deterministic encoding requirements of
+    /// RFC 8949 section 4.2.1, so equal values always give equal bytes: map
+    /// keys are sorted by their encoding and may not repeat. Lengths and
+    /// numbers are always definite and as short as possible.
+    pub fn to_deterministic_vec(&self) -> Result<Vec<u8>, encode::Error<io::Error>> {
+        let mut buf = Vec::new();
+        encode_deterministic(self, &mut Encoder::new(IoWrite(&mut buf)))?;
+        Ok(buf)
+    }
+
+    /// The value of the entry with the text key `key`.
+    pub fn get(&self, key: &str) -> Option<&Cbor> {
+        match self {
+            Cbor::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| matches!(k, Cbor::Text(k) if k == key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Cbor {
+    fn from(s: &str) -> Self {
+        Cbor::Text(s.to_owned())
+    }
+}
+
+impl From<String> for Cbor {
+    fn from(s: String) -> Self {
+        Cbor::Text(s)
+    }
+}
+
+impl From<Vec<u8>> for Cbor {
+    fn from(bytes: Vec<u8>) -> Self {
+        Cbor::Bytes(bytes)
+    }
+}
+
+impl From<i64> for Cbor {
+    fn from(n: i64) -> Self {
+        Cbor::Integer(n.into())
+    }
+}
+
+impl From<u64> for Cbor {
+    fn from(n: u64) -> Self {
+        Cbor::Integer(n.into())
+    }
+}
+
+impl From<f64> for Cbor {
+    fn from(n: f64) -> Self {
+        Cbor::Float(n)
+    }
+}
+
+impl From<bool> for Cbor {
+    fn from(b: bool) -> Self {
+        Cbor::Bool(b)
+    }
+}
+
+impl<'b> Decode<'b> for Cbor {
+    fn decode(d: &mut Decoder<'b>) -> Result<Self, decode::Error> {
+        decode_inner(d, 0)
+    }
+}
+
+fn decode_inner(d: &mut Decoder, depth: usize) -> Result<Cbor, decode::Error> {
+    if depth > MAX_DEPTH {
+        return Err(decode::Error::Message("nesting too deep"));
+    }
+    Ok(match d.datatype()? {
+        Type::Bool => Cbor::Bool(d.bool()?),
+        Type::U8 | Type::U16 | Type::U32 | Type::U64 => Cbor::Integer(d.u64()?.into()),
+        // `i64` reads every width, the narrower readers reject e.g. -256
+        Type::I8 | Type::I16 | Type::I32 | Type::I64 => Cbor::Integer(d.i64()?.into()),
+        Type::F16 => Cbor::Float(d.f16()?.into()),
+        Type::F32 => Cbor::Float(d.f32()?.into()),
+        Type::F64 => Cbor::Float(d.f64()?),
+        Type::Bytes | Type::BytesIndef => {
+            let mut bytes = Vec::new();
+            for chunk in d.bytes_iter()? {
+                bytes.extend_from_slice(chunk?);
+            }
+            Cbor::Bytes(bytes)
+        }
+        Type::String | Type::StringIndef => {
+            let mut buf = String::new();
+            for chunk in d.str_iter()? {
+                buf.push_str(chunk?);
+            }
+            Cbor::Text(buf)
+        }
+        Type::Array | Type::ArrayIndef => {
+            let mut result = Vec::new();
+            match d.array()? {
+                Some(n) => {
+                    for _ in 0..n {
+                        result.push(decode_inner(d, depth + 1)?);
+                    }
+                }
+                None => {
+                    while d.datatype()? != Type::Break {
+                        result.push(decode_inner(d, depth + 1)?);
+                    }
+                    skip_break(d);
+                }
+            }
+            Cbor::Array(result)
+        }
+        Type::Map | Type::MapIndef => {
+            let mut result = Vec::new();
+            let mut entry = |d: &mut Decoder| -> Result<(), decode::Error> {
+                let key = decode_inner(d, depth + 1)?;
+                result.push((key, decode_inner(d, depth + 1)?));
+                Ok(())
+            };
+            match d.map()? {
+                Some(n) => {
+                    for _ in 0..n {
+                        entry(d)?;
+                    }
+                }
+                None => {
+                    while d.datatype()? != Type::Break {
+                        entry(d)?;
+                    }
+                    skip_break(d);
+                }
+            }
+            Cbor::Map(result)
+        }
+        Type::Tag => {
+            let tag = tag_number(d.tag()?);
+            Cbor::Tag(tag, Box::new(decode_inner(d, depth + 1)?))
+        }
+        Type::Simple => return Err(decode::Error::Message("Simple not yet supported")),
+        Type::Break => return Err(decode::Error::Message("unexpected break")),
+        Type::Null => {
+            d.null()?;
+            Cbor::Null
+        }
+        Type::Undefined => {
+            d.undefined()?;
+            Cbor::Null
+        }
+        t @ Type::Unknown(_) => return Err(decode::Error::TypeMismatch(t, "unknown cbor type")),
+    })
+}
+
+pub(crate) fn skip_break(d: &mut Decoder) {
+    d.set_position(d.position() + 1);
+}
+
+fn tag_number(tag: Tag) -> u64 {
+    match tag {
+        Tag::DateTime => 0,
+        Tag::Timestamp => 1,
+        Tag::PosBignum => 2,
+        Tag::NegBignum => 3,
+        Tag::Decimal => 4,
+        Tag::Bigfloat => 5,
+        Tag::ToBase64Url => 21,
+        Tag::ToBase64 => 22,
+        Tag::ToBase16 => 23,
+        Tag::Cbor => 24,
+        Tag::Uri => 32,
+        Tag::Base64Url => 33,
+        Tag::Base64 => 34,
+        Tag::Regex => 35,
+        Tag::Mime => 36,
+        Tag::Unassigned(n) => n,
+    }
+}
+
+impl Encode for Cbor {
+    fn encode<W: encode::Write>(&self, e: &mut Encoder<W>) -> Result<(), encode::Error<W::Error>> {
+        match self {
+            Cbor::Null => {
+                e.null()?;
+            }
+            Cbor::Bool(b) => {
+                e.bool(*b)?;
+            }
+            Cbor::Integer(n) => {
+                if let Ok(n) = u64::try_from(*n) {
+                    e.u64(n)?;
+                } else if let Ok(n) = i64::try_from(*n) {
+                    e.i64(n)?;
+                } else {
+                    return Err(encode::Error::Message("integer out of range"));
+                }
+            }
+            Cbor::Float(n) => encode_float(*n, e)?,
+            Cbor::Bytes(bytes) => {
+                e.bytes(bytes)?;
+            }
+            Cbor::Text(s) => {
+                e.str(s)?;
+            }
+            Cbor::Array(a) => {
+                e.array(a.len() as u64)?;
+                for element in a {
+                    element.encode(e)?;
+                }
+            }
+            Cbor::Map(m) => {
+                e.map(m.len() as u64)?;
+                for (k, v) in m {
+                    k.encode(e)?;
+                    v.encode(e)?;
+                }
+            }
+            Cbor::Tag(tag, value) => {
+                e.tag(Tag::Unassigned(*tag))?;
+                value.encode(e)?;
+            }
+        };
+        Ok(())
+    }
+}
+
+fn encode_deterministic<W: encode::Write>(
+    value: &Cbor,
+    e: &mut Encoder<W>,
+) -> Result<(), encode::Error<W::Error>> {
+    match value {
+        Cbor::Array(a) => {
+            e.array(a.len() as u64)?;
+            for element in a {
+                encode_deterministic(element, e)?;
+            }
+        }
+        Cbor::Map(m) => {
+            let mut entries = Vec::with_capacity(m.len());
+            for (k, v) in m {
+                let k = k.to_deterministic_vec().map_err(|e| match e {
+                    encode::Error::Message(m) => encode::Error::Message(m),
+                    // a `Vec` is never full
+                    _ => unreachable!(),
+                })?;
+                entries.push((k, v));
+            }
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(encode::Error::Message("duplicate map key"));
+            }
+            e.map(entries.len() as u64)?;
+            for (k, v) in entries {
+                e.encode(data::Cbor::from(&k[..]))?;
+                encode_deterministic(v, e)?;
+            }
+        }
+        Cbor::Tag(tag, value) => {
+            e.tag(Tag::Unassigned(*tag))?;
+            encode_deterministic(value, e)?;
+        }
+        value => value.encode(e)?,
+    }
+    Ok(())
+}
+
+/// Encodes `n` as the shortest CBOR float that holds it exactly, and NaN
+/// as `0xf97e00`.
+pub(crate) fn encode_float<W: encode::Write>(
+    n: f64,
+    e: &mut Encoder<W>,
+) -> Result<(), encode::Error<W::Error>> {
+    let single = n as f32;
+    if n.is_nan() {
+        e.f16(f32::NAN)?;
+    } else if f64::from(single) != n {
+        e.f64(n)?;
+    } else if f32::from(half::f16::from_f32(single)) != single {
+        e.f32(single)?;
+    } else {
+        e.f16(single)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_and_bytes() {
+        let value = Cbor::Map(vec![
+            (Cbor::from("text"), Cbor::from("#RHVubg==")),
+            (Cbor::from("bytes"), Cbor::from(b"Dunn".to_vec())),
+            (Cbor::from(1u64), Cbor::from(-1.5)),
+            (Cbor::from(-1i64), Cbor::Tag(1000, Box::new(Cbor::Null))),
+        ]);
+        let buf = value.to_vec().unwrap();
+        assert_eq!(
+            buf,
+            b"\xa4\x64text\x69#RHVubg==\x65bytes\x44Dunn\x01\xf9\xbe\x00\x20\xd9\x03\xe8\xf6"
+        );
+        assert_eq!(Cbor::from_slice(&buf).unwrap(), value);
+        assert_eq!(value.get("text"), Some(&Cbor::from("#RHVubg==")));
+        assert_eq!(value.get("1"), None);
+
+        assert!(Cbor::Integer(i128::from(u64::MAX) + 1).to_vec().is_err());
+        assert!(Cbor::Integer(i128::from(u64::MAX) + 1)
+            .to_deterministic_vec()
+            .is_err());
+        assert!(Cbor::Integer(i128::from(i64::MIN) - 1).to_vec().is_err());
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // RFC 8949, appendix A
+    #[test]
+    fn test_deterministic_numbers() {
+        let cases: &[(Cbor, &str)] = &[
+            (Cbor::from(0u64), "00"),
+            (Cbor::from(10u64), "0a"),
+            (Cbor::from(23u64), "17"),
+            (Cbor::from(24u64), "1818"),
+            (Cbor::from(100u64), "1864"),
+            (Cbor::from(1000u64), "1903e8"),
+            (Cbor::from(1000000u64), "1a000f4240"),
+            (Cbor::from(1000000000000u64), "1b000000e8d4a51000"),
+            (Cbor::from(u64::MAX), "1bffffffffffffffff"),
+            (Cbor::from(-1i64), "20"),
+            (Cbor::from(-100i64), "3863"),
+            (Cbor::from(-1000i64), "3903e7"),
+            (Cbor::from(0.0), "f90000"),
+            (Cbor::from(-0.0), "f98000"),
+            (Cbor::from(1.0), "f93c00"),
+            (Cbor::from(1.1), "fb3ff199999999999a"),
+            (Cbor::from(1.5), "f93e00"),
+            (Cbor::from(65504.0), "f97bff"),
+            (Cbor::from(100000.0), "fa47c35000"),
+            (Cbor::from(3.4028234663852886e38), "fa7f7fffff"),
+            (Cbor::from(1.0e300), "fb7e37e43c8800759c"),
+            (Cbor::from(5.960464477539063e-8), "f90001"),
+            (Cbor::from(0.00006103515625), "f90400"),
+            (Cbor::from(-4.0), "f9c400"),
+            (Cbor::from(-4.1), "fbc010666666666666"),
+            (Cbor::from(f64::INFINITY), "f97c00"),
+            (Cbor::from(f64::NAN), "f97e00"),
+            (Cbor::from(f64::NEG_INFINITY), "f9fc00"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                value.to_deterministic_vec().unwrap(),
+                hex(expected),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    // RFC 8949, section 4.2.1
+    #[test]
+    fn test_deterministic_maps() {
+        let keys = [
+            Cbor::from(false),
+            Cbor::Array(vec![Cbor::from(-1i64)]),
+            Cbor::Array(vec![Cbor::from(100u64)]),
+            Cbor::from("aa"),
+            Cbor::from("z"),
+            Cbor::from(-1i64),
+            Cbor::from(100u64),
+            Cbor::from(10u64),
+        ];
+        let map = |keys: &[Cbor]| Cbor::Map(keys.iter().map(|k| (k.clone(), Cbor::Null)).collect());
+        let mut reversed = keys.clone();
+        reversed.reverse();
+        let expected = hex("a80af61864f620f6617af6626161f6811864f68120f6f4f6");
+        assert_eq!(map(&keys).to_deterministic_vec().unwrap(), expected);
+        assert_eq!(map(&reversed).to_deterministic_vec().unwrap(), expected);
+
+        // nested maps are sorted too, indefinite lengths become definite
+        let indefinite = hex("bf6162bf616260616100ff616180ff");
+        let value = Cbor::from_slice(&indefinite).unwrap();
+        assert_eq!(
+            value.to_deterministic_vec().unwrap(),
+            hex("a26161806162a2616100616260")
+        );
+
+        let duplicate = map(&[Cbor::from(1u64), Cbor::from(2u64), Cbor::from(1u64)]);
+        assert!(duplicate.to_vec().is_ok());
+        assert!(duplicate.to_deterministic_vec().is_err());
+    }
+}